@@ -0,0 +1,143 @@
+use std::{path::PathBuf, time::Duration};
+
+use contiguous_memory_allocation::units::parse_duration;
+
+pub(crate) const USAGE: &str = "\
+Usage: contiguous_memory_allocation <COMMAND> <FILE> [OPTIONS]
+
+Commands:
+  run <CONFIG>      step through a single strategy
+  compare <CONFIG>  step through strategies side by side
+  batch <CONFIG>    run every scenario without stopping and print a summary
+  replay <RUN>      step through a run written by `export`
+  export <CONFIG>   simulate and write the run to --out
+
+Options:
+  --scenario <NAME>      only run this scenario from the config
+  --strategy <NAMES>     comma separated strategies (best, next, worst, first, segregated, tlsf, slab, two-ended, fixed),
+                         same as --strategies
+  --interval <TIME>      auto play, advancing every TIME (e.g. 2s, 500ms)
+  --non-interactive      print every frame without waiting for input
+  --out <FILE>           where `export` writes the run
+  --<KEY> <VALUE>        override any config key, e.g. --memory-max 4MB
+  --help                 print this message";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Command {
+    Run,
+    Compare,
+    Batch,
+    Replay,
+    Export,
+}
+
+/// How the GUI moves from one frame to the next.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Playback {
+    /// wait for the user to press enter.
+    Step,
+    /// wait for the given interval.
+    Auto(Duration),
+    /// don't wait at all.
+    NonInteractive,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Args {
+    pub command: Command,
+    pub path: PathBuf,
+    pub scenario: Option<String>,
+    /// config keys to override, in the order they were passed.
+    pub overrides: Vec<(String, String)>,
+    pub playback: Playback,
+    pub out: Option<PathBuf>,
+}
+
+/// Parses the process arguments, without the executable name.
+pub(crate) fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("run") => Command::Run,
+        Some("compare") => Command::Compare,
+        Some("batch") => Command::Batch,
+        Some("replay") => Command::Replay,
+        Some("export") => Command::Export,
+        Some(other) => return Err(format!("Unknown command `{other}`")),
+        None => return Err("Missing a command".to_string()),
+    };
+    let mut out = Args {
+        command,
+        path: PathBuf::new(),
+        scenario: None,
+        overrides: vec![],
+        playback: Playback::Step,
+        out: None,
+    };
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            if path.replace(PathBuf::from(&arg)).is_some() {
+                return Err(format!("Unexpected argument `{arg}`"));
+            }
+            continue;
+        };
+        // flags may be passed as `--flag value` or `--flag=value`.
+        let (flag, inline_value) = match flag.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (flag, None),
+        };
+        if flag == "non-interactive" {
+            out.playback = Playback::NonInteractive;
+            continue;
+        }
+        let value = inline_value
+            .or_else(|| args.next())
+            .ok_or_else(|| format!("Missing a value for --{flag}"))?;
+        match flag {
+            "scenario" => out.scenario = Some(value.to_lowercase()),
+            "strategy" => out.overrides.push(("strategies".to_string(), value)),
+            "interval" => {
+                let ms = parse_duration(&value).ok_or(format!("Bad interval `{value}`"))?;
+                out.playback = Playback::Auto(Duration::from_millis(ms.into()));
+            }
+            "out" => out.out = Some(PathBuf::from(value)),
+            key => out.overrides.push((key.replace('-', "_"), value)),
+        }
+    }
+    out.path = path.ok_or("Missing the file to read")?;
+    if out.command == Command::Export && out.out.is_none() {
+        return Err("export needs somewhere to write to, pass --out".to_string());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<Args, String> {
+        parse_args(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args("compare config.toml --scenario Big-Memory --strategy best,next --memory-max=4MB --num-proc 3 --interval 500ms"),
+            Ok(Args {
+                command: Command::Compare,
+                path: PathBuf::from("config.toml"),
+                scenario: Some("big-memory".to_string()),
+                overrides: vec![
+                    ("strategies".to_string(), "best,next".to_string()),
+                    ("memory_max".to_string(), "4MB".to_string()),
+                    ("num_proc".to_string(), "3".to_string()),
+                ],
+                playback: Playback::Auto(Duration::from_millis(500)),
+                out: None,
+            })
+        );
+        assert!(args("export config.toml").is_err());
+        assert!(args("explode config.toml").is_err());
+        assert!(args("run config.toml --num-proc").is_err());
+    }
+}
@@ -1,53 +1,319 @@
 use std::collections::HashMap;
 
+use strategies::{
+    Backing, Compaction, ListOrder, Partitions, Queueing, RoverPolicy, SlabOptions, TwoEndedOptions,
+};
+
 pub mod strategies;
+pub mod units;
 
-#[derive(Debug, PartialEq, Eq)]
+/// The simulation settings. Sizes are in bytes and
+/// `max_proc_time` is in ms.
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Config {
-    pub memory_max: u32,
-    pub proc_size_max: u32,
+    pub memory_max: u64,
+    pub proc_size_max: u64,
     pub num_proc: u32,
     pub max_proc_time: u32,
+    /// every process has to start at a multiple of this,
+    /// has to be a power of two.
+    pub alignment: u64,
+    /// holes smaller than this aren't worth keeping, so a
+    /// process that would leave one behind gets it as well.
+    pub min_fragment: u64,
+    /// every process gets a whole number of these, e.g.
+    /// 30KB is rounded up to 32KB with a 4KB unit.
+    pub allocation_unit: u64,
+    /// how the `fixed` strategy cuts up memory.
+    pub partitions: Partitions,
+    /// whether processes wait for a partition in one line,
+    /// or a line per partition.
+    pub partition_queues: Queueing,
+    /// requests up to this big come out of slabs with the `slab` strategy.
+    pub slab_threshold: u64,
+    /// how big each of those slabs is.
+    pub slab_size: u64,
+    /// what the `slab` strategy uses for everything else.
+    pub slab_backing: Backing,
+    /// processes this big or bigger go at the top of memory
+    /// with the `two-ended` strategy, the rest at the bottom.
+    pub two_ended_threshold: u64,
+    /// what picks the hole for the `two-ended` strategy.
+    pub two_ended_base: Backing,
+    /// where the `next` strategy's rover goes after placing a process.
+    pub rover_policy: RoverPolicy,
+    /// the order the `first` and `next` strategies keep their holes in.
+    pub free_list_order: ListOrder,
+    /// what to do when the request at the front of the queue doesn't fit.
+    pub compaction: Compaction,
+    /// how much incremental compaction can move each tick.
+    pub compaction_budget: u64,
+    /// names of the strategies to run, in the order to show them.
+    pub strategies: Vec<String>,
+}
+
+/// A line of the config file that we care about.
+enum Line {
+    /// `[name]`, starts a new scenario.
+    Section(String),
+    /// `key = value`
+    Pair(String, String),
 }
 
 // this would have been a lot cleaner if I used the serde library
 // but I opted not too, for your sanity's sake.
-pub fn parse_config(s: &str) -> Option<Config> {
-    let conf: HashMap<String, String> = s
-        .lines()
+fn parse_lines(s: &str) -> Result<Vec<Line>, ConfigError> {
+    s.lines()
         .map(|line| {
             line.chars()
                 .filter(|i| !i.is_whitespace())
                 .collect::<String>()
         })
-        .filter(|i| !i.starts_with("#"))
+        .filter(|i| !i.is_empty() && !i.starts_with("#"))
         .map(|line| line.to_lowercase())
-        .map(|pair| {
-            pair.split("=")
+        .map(|line| {
+            if let Some(name) = line.strip_prefix("[").and_then(|i| i.strip_suffix("]")) {
+                return Ok(Line::Section(name.to_string()));
+            }
+            line.split("=")
                 .map(|item| item.to_string())
                 .collect::<Vec<_>>()
+                .try_into()
+                .map(|[a, b]: [String; 2]| Line::Pair(a, b))
+                .map_err(|_| ConfigError::BadLine(line))
         })
-        .map(|pair| pair.try_into().map(|[a, b]: [String; 2]| (a, b)))
-        .collect::<Result<HashMap<_, _>, _>>()
-        .ok()?;
-    Some(Config {
-        memory_max: conf
-            .get("memory_max")
-            .map(|i| i.parse().expect("COULDN'T PARSE MEMORY_MAX"))
-            .unwrap_or(1024),
-        proc_size_max: conf
-            .get("proc_size_max")
-            .map(|i| i.parse().expect("COULDN'T PARSE PROC_SIZE_MAX"))
-            .unwrap_or(1024),
-        num_proc: conf
-            .get("num_proc")
-            .map(|i| i.parse().expect("COULDN'T PARSE NUM_PROC"))
-            .unwrap_or(10),
-        max_proc_time: conf
-            .get("max_proc_time")
-            .map(|i| i.parse().expect("COULDN'T PARSE MAX_PROC_TIME"))
-            .unwrap_or(10_000),
-    })
+        .collect()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            memory_max: 1024 * 1024,
+            proc_size_max: 1024 * 1024,
+            num_proc: 10,
+            max_proc_time: 10_000,
+            alignment: 1,
+            min_fragment: 0,
+            allocation_unit: 1,
+            partitions: Partitions::default(),
+            partition_queues: Queueing::default(),
+            slab_threshold: SlabOptions::default().threshold,
+            slab_size: SlabOptions::default().size,
+            slab_backing: Backing::default(),
+            two_ended_threshold: TwoEndedOptions::default().threshold,
+            two_ended_base: TwoEndedOptions::default().base,
+            rover_policy: RoverPolicy::default(),
+            free_list_order: ListOrder::default(),
+            compaction: Compaction::default(),
+            compaction_budget: 64 * 1024,
+            strategies: vec!["best".to_string(), "next".to_string(), "worst".to_string()],
+        }
+    }
+}
+
+/// Why a config key couldn't be set.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    UnknownKey(String),
+    BadValue(String),
+    /// a line that's neither `key = value` nor `[scenario]`.
+    BadLine(String),
+    /// a key that can only be one of a few things, and what they are.
+    NotOneOf(String, &'static str),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::UnknownKey(key) => write!(f, "UNKNOWN CONFIG KEY {}", key.to_uppercase()),
+            ConfigError::BadValue(key) => write!(f, "COULDN'T PARSE {}", key.to_uppercase()),
+            ConfigError::BadLine(line) => write!(f, "COULDN'T PARSE THE LINE {line}"),
+            ConfigError::NotOneOf(key, options) => {
+                write!(f, "{} HAS TO BE ONE OF {options}", key.to_uppercase())
+            }
+        }
+    }
+}
+
+/// the strategies another one can be built on, see [`Backing`].
+const BACKINGS: &str = "BEST, FIRST, WORST OR SEGREGATED";
+
+/// a strategy another one is built on, e.g. `best`.
+fn parse_backing(value: &str) -> Option<Backing> {
+    match value.to_lowercase().as_str() {
+        "best" => Some(Backing::Best),
+        "first" => Some(Backing::First),
+        "worst" => Some(Backing::Worst),
+        "segregated" => Some(Backing::Segregated),
+        _ => None,
+    }
+}
+
+impl Config {
+    /// Sets a single config key (e.g. `memory_max`) from its textual value,
+    /// the same way it would be read from the config file.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let key = key.to_lowercase();
+        let bad_value = || ConfigError::BadValue(key.clone());
+        match key.as_str() {
+            "memory_max" => self.memory_max = units::parse_size(value).ok_or_else(bad_value)?,
+            "proc_size_max" => {
+                self.proc_size_max = units::parse_size(value).ok_or_else(bad_value)?
+            }
+            "num_proc" => self.num_proc = value.parse().map_err(|_| bad_value())?,
+            "max_proc_time" => {
+                self.max_proc_time = units::parse_duration(value).ok_or_else(bad_value)?
+            }
+            "alignment" => {
+                self.alignment = units::parse_size(value)
+                    .filter(|align| align.is_power_of_two())
+                    .ok_or_else(bad_value)?
+            }
+            "min_fragment" => self.min_fragment = units::parse_size(value).ok_or_else(bad_value)?,
+            "allocation_unit" => {
+                self.allocation_unit = units::parse_size(value)
+                    .filter(|unit| *unit > 0)
+                    .ok_or_else(bad_value)?
+            }
+            "partitions" => {
+                // a bare number is how many equal partitions to
+                // make, anything else is a list of their sizes.
+                self.partitions = match value.parse() {
+                    Ok(count) if count > 0 => Partitions::Equal(count),
+                    Ok(_) => return Err(bad_value()),
+                    Err(_) => Partitions::Sizes(
+                        value
+                            .split(',')
+                            .map(units::parse_size)
+                            .collect::<Option<_>>()
+                            .ok_or_else(bad_value)?,
+                    ),
+                }
+            }
+            "partition_queues" => {
+                self.partition_queues = match value.to_lowercase().as_str() {
+                    "single" => Queueing::Single,
+                    "per-partition" => Queueing::PerPartition,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "slab_threshold" => {
+                self.slab_threshold = units::parse_size(value).ok_or_else(bad_value)?
+            }
+            "slab_size" => {
+                self.slab_size = units::parse_size(value)
+                    .filter(|size| *size > 0)
+                    .ok_or_else(bad_value)?
+            }
+            "slab_backing" => {
+                self.slab_backing = parse_backing(value)
+                    .ok_or_else(|| ConfigError::NotOneOf(key.clone(), BACKINGS))?
+            }
+            "two_ended_threshold" => {
+                self.two_ended_threshold = units::parse_size(value).ok_or_else(bad_value)?
+            }
+            "two_ended_base" => {
+                self.two_ended_base = parse_backing(value)
+                    .ok_or_else(|| ConfigError::NotOneOf(key.clone(), BACKINGS))?
+            }
+            "rover_policy" => {
+                self.rover_policy = match value.to_lowercase().as_str() {
+                    "stay" => RoverPolicy::Stay,
+                    "past" => RoverPolicy::Past,
+                    "reset" => RoverPolicy::Reset,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "free_list_order" => {
+                self.free_list_order = match value.to_lowercase().as_str() {
+                    "address" => ListOrder::Address,
+                    "lifo" => ListOrder::Lifo,
+                    "fifo" => ListOrder::Fifo,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "compaction" => {
+                self.compaction = match value.to_lowercase().as_str() {
+                    "off" => Compaction::Off,
+                    "full" => Compaction::Full,
+                    "partial" => Compaction::Partial,
+                    "incremental" => Compaction::Incremental(self.compaction_budget),
+                    _ => return Err(bad_value()),
+                }
+            }
+            "compaction_budget" => {
+                self.compaction_budget = units::parse_size(value).ok_or_else(bad_value)?;
+                // it might've been set to incremental before the budget was.
+                if let Compaction::Incremental(budget) = &mut self.compaction {
+                    *budget = self.compaction_budget;
+                }
+            }
+            "strategies" => {
+                let strategies = value
+                    .split(',')
+                    .map(|name| name.trim().to_lowercase())
+                    .collect::<Vec<_>>();
+                if strategies
+                    .iter()
+                    .any(|name| strategies::by_name(name).is_none())
+                {
+                    return Err(bad_value());
+                }
+                self.strategies = strategies;
+            }
+            _ => return Err(ConfigError::UnknownKey(key)),
+        }
+        Ok(())
+    }
+}
+
+fn config_from(conf: &HashMap<String, String>) -> Result<Config, ConfigError> {
+    let mut config = Config::default();
+    for (key, value) in conf {
+        match config.set(key, value) {
+            // unknown keys are ignored so old configs keep working.
+            Ok(()) | Err(ConfigError::UnknownKey(_)) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(config)
+}
+
+/// Parses the config at the top of the file, before any
+/// `[scenario]` section starts.
+pub fn parse_config(s: &str) -> Result<Config, ConfigError> {
+    let conf = parse_lines(s)?
+        .into_iter()
+        .map_while(|line| match line {
+            Line::Pair(key, value) => Some((key, value)),
+            Line::Section(_) => None,
+        })
+        .collect();
+    config_from(&conf)
+}
+
+/// Parses every `[scenario]` section in the file, in the order they're
+/// written. Keys at the top of the file are shared defaults that each
+/// scenario can override. A file without any sections has no scenarios,
+/// use [`parse_config`] for those.
+pub fn parse_scenarios(s: &str) -> Result<Vec<(String, Config)>, ConfigError> {
+    let mut defaults = HashMap::new();
+    let mut scenarios: Vec<(String, HashMap<String, String>)> = vec![];
+    for line in parse_lines(s)? {
+        match (line, scenarios.last_mut()) {
+            (Line::Section(name), _) => scenarios.push((name, defaults.clone())),
+            (Line::Pair(key, value), Some((_, conf))) => {
+                conf.insert(key, value);
+            }
+            (Line::Pair(key, value), None) => {
+                defaults.insert(key, value);
+            }
+        }
+    }
+    scenarios
+        .into_iter()
+        .map(|(name, conf)| Ok((name, config_from(&conf)?)))
+        .collect()
 }
 
 #[test]
@@ -61,11 +327,121 @@ fn test_parse_config() {
             num_proc = 32
             max_proc_time = 9822"
         ),
-        Some(Config {
-            memory_max: 32,
-            proc_size_max: 78,
+        Ok(Config {
+            memory_max: 32 * 1024,
+            proc_size_max: 78 * 1024,
             num_proc: 32,
             max_proc_time: 9822,
+            ..Config::default()
+        })
+    )
+}
+
+#[test]
+fn test_parse_config_units() {
+    assert_eq!(
+        parse_config(
+            "MEMORY_MAX = 4MB
+            PROC_SIZE_MAX = 256KB
+            NUM_PROC = 12
+            MAX_PROC_TIME = 10s"
+        ),
+        Ok(Config {
+            memory_max: 4 * 1024 * 1024,
+            proc_size_max: 256 * 1024,
+            num_proc: 12,
+            max_proc_time: 10_000,
+            ..Config::default()
         })
+    );
+    assert_eq!(
+        Config::default().set("alignment", "24B"),
+        Err(ConfigError::BadValue("alignment".to_string()))
     )
 }
+
+#[test]
+fn test_parse_scenarios() {
+    let file = "# shared by everyone
+        memory_max = 2MB
+        num_proc = 20
+
+        [small-procs]
+        proc_size_max = 16
+
+        [Long-Lived]
+        num_proc = 5
+        strategies = first, worst
+        max_proc_time = 1min
+        alignment = 4KB
+        partitions = 64KB, 512
+        partition_queues = Per-Partition
+        slab_backing = Segregated
+        two_ended_base = worst
+        rover_policy = Reset
+        free_list_order = LIFO
+        compaction = incremental
+        compaction_budget = 16";
+    assert_eq!(
+        parse_scenarios(file),
+        Ok(vec![
+            (
+                "small-procs".to_string(),
+                Config {
+                    memory_max: 2 * 1024 * 1024,
+                    proc_size_max: 16 * 1024,
+                    num_proc: 20,
+                    max_proc_time: 10_000,
+                    ..Config::default()
+                }
+            ),
+            (
+                "long-lived".to_string(),
+                Config {
+                    memory_max: 2 * 1024 * 1024,
+                    proc_size_max: 1024 * 1024,
+                    num_proc: 5,
+                    max_proc_time: 60_000,
+                    alignment: 4096,
+                    min_fragment: 0,
+                    allocation_unit: 1,
+                    partitions: Partitions::Sizes(vec![64 * 1024, 512 * 1024]),
+                    partition_queues: Queueing::PerPartition,
+                    slab_threshold: 8 * 1024,
+                    slab_size: 64 * 1024,
+                    slab_backing: Backing::Segregated,
+                    two_ended_threshold: 64 * 1024,
+                    two_ended_base: Backing::Worst,
+                    rover_policy: RoverPolicy::Reset,
+                    free_list_order: ListOrder::Lifo,
+                    compaction: Compaction::Incremental(16 * 1024),
+                    compaction_budget: 16 * 1024,
+                    strategies: vec!["first".to_string(), "worst".to_string()],
+                }
+            ),
+        ])
+    );
+    assert_eq!(parse_config(file).unwrap().num_proc, 20);
+}
+
+#[test]
+fn test_bad_config() {
+    assert_eq!(
+        parse_config("MEMORY_MAX = abc"),
+        Err(ConfigError::BadValue("memory_max".to_string()))
+    );
+    assert_eq!(
+        parse_scenarios("[busy]\nNUM_PROC = lots"),
+        Err(ConfigError::BadValue("num_proc".to_string()))
+    );
+    assert_eq!(
+        parse_config("TWO_ENDED_BASE = next")
+            .unwrap_err()
+            .to_string(),
+        "TWO_ENDED_BASE HAS TO BE ONE OF BEST, FIRST, WORST OR SEGREGATED"
+    );
+    assert_eq!(
+        parse_config("MEMORY_MAX"),
+        Err(ConfigError::BadLine("memory_max".to_string()))
+    );
+}
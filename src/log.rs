@@ -1,56 +1,98 @@
-use std::{io::Stdout, ops::Add, time::Duration};
-
-use rand::{seq::SliceRandom, thread_rng, Rng};
-
 use contiguous_memory_allocation::{
-    strategies::{MemoryRegion, MemoryRequest, Pid},
-    Config,
-};
-use crossterm::event::{self, Event, KeyCode};
-use ratatui::{
-    backend::CrosstermBackend,
-    layout::{self, Constraint, Direction, Layout},
-    style::{Color, Style},
-    widgets::{
-        canvas::{Canvas, Label, Rectangle},
-        Bar, BarChart, BarGroup, Paragraph,
+    strategies::{
+        Addr, ClassStats, EndStats, Frames, MemoryRegion, Owner, Pid, Size, SlabInfo, Snapshot,
     },
-    Terminal,
+    units::format_size,
 };
 
+use crate::cli::Playback;
+
 struct Gui {
-    // a list of different memories the program has had over it's
-    // lifetime. You can get the Nth state of RAM by indexing to mem[n]
-    frame_info: [Vec<(Vec<MemoryRegion>, Vec<MemoryRequest>)>; 3],
+    // a list of different memories each strategy has had over it's
+    // lifetime, next to the strategy's label. You can get the Nth
+    // state of RAM by indexing to frame_info[strategy].1[n]
+    frame_info: Vec<(String, Frames)>,
 }
 
 #[derive(PartialEq, Eq)]
 enum ProcessOrFree {
-    Process(Pid, i32),
+    Process(Pid, u64),
+    /// wasted space a process holds, e.g. to line it up.
+    Padding(Pid, u64),
+    /// what a process was given from rounding it up to whole allocation units.
+    Rounding(Pid, u64),
+    /// empty slots in a slab, for objects of this size.
+    Slab(Size),
     Free,
 }
 
 impl Gui {
-    fn new(frame_info: [Vec<(Vec<MemoryRegion>, Vec<MemoryRequest>)>; 3]) -> Self {
+    fn new(frame_info: Vec<(String, Frames)>) -> Self {
         Self { frame_info }
     }
-    fn frames(mem: &[MemoryRegion]) -> Vec<(ProcessOrFree, u32)> {
+    fn frames(mem: &[MemoryRegion]) -> Vec<(ProcessOrFree, Size)> {
         mem.windows(2)
             .map(|item| {
                 let [region, next_region]: [MemoryRegion; 2] = item.try_into().unwrap();
-                if let Some((pid, lifetime)) = region.0 {
-                    (
-                        ProcessOrFree::Process(pid, lifetime),
-                        next_region.1 - region.1,
-                    )
-                } else {
-                    (ProcessOrFree::Free, next_region.1 - region.1)
-                }
+                let owner = match region.0 {
+                    Owner::Process(pid, lifetime) => ProcessOrFree::Process(pid, lifetime),
+                    Owner::Padding(pid, lifetime) => ProcessOrFree::Padding(pid, lifetime),
+                    Owner::Rounding(pid, lifetime) => ProcessOrFree::Rounding(pid, lifetime),
+                    Owner::Slab(object_size) => ProcessOrFree::Slab(object_size),
+                    Owner::Free | Owner::End => ProcessOrFree::Free,
+                };
+                (owner, next_region.1 - region.1)
             })
             .collect::<Vec<_>>()
     }
-    fn stats(info: &[(ProcessOrFree, u32)], requests: &[MemoryRequest]) -> String {
-        let total_free: u32 = info
+    /// how many slabs there are, how many of their slots are in use
+    /// and how much they waste, e.g. `Slabs: 2 (5/12 slots used, 1KB wasted)`.
+    fn slab_stats(slabs: &[SlabInfo]) -> String {
+        let used: u32 = slabs.iter().map(|slab| slab.used).sum();
+        let slots: u32 = slabs.iter().map(|slab| slab.slots).sum();
+        let waste: Size = slabs.iter().map(|slab| slab.waste).sum();
+        format!(
+            "Slabs: {} ({used}/{slots} slots used, {} wasted)",
+            slabs.len(),
+            format_size(waste)
+        )
+    }
+    /// how many requests of each size class were served from their own class,
+    /// e.g. `Size Classes: 8B 1/3, 16B 1/2`.
+    fn class_stats(classes: &[ClassStats]) -> String {
+        format!(
+            "Size Classes: {}",
+            classes
+                .iter()
+                .map(|class| {
+                    format!(
+                        "{} {}/{}",
+                        format_size(class.size),
+                        class.hits,
+                        class.hits + class.misses
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+    /// the holes at each end of memory, e.g.
+    /// `Low End: 2 hole(s) (12KB), High End: 1 hole(s) (64KB)`.
+    fn end_stats((low, high): (EndStats, EndStats)) -> String {
+        format!(
+            "Low End: {} hole(s) ({}), High End: {} hole(s) ({})",
+            low.holes,
+            format_size(low.free),
+            high.holes,
+            format_size(high.free)
+        )
+    }
+    fn stats(info: &[(ProcessOrFree, Size)], frame: &Snapshot) -> String {
+        let requests = frame.requests();
+        let slabs = frame.slabs();
+        let moved = frame.moved();
+        let moving = frame.moving();
+        let total_free: Size = info
             .iter()
             .filter_map(|(process_or_free, size)| {
                 if *process_or_free == ProcessOrFree::Free {
@@ -70,105 +112,324 @@ impl Gui {
                 }
             })
             .sum();
-        let total_full: u32 = info.iter().map(|(_, size)| size).sum();
+        let total_padding: Size = info
+            .iter()
+            .filter(|(process_or_free, _)| {
+                matches!(
+                    process_or_free,
+                    ProcessOrFree::Padding(..) | ProcessOrFree::Rounding(..)
+                )
+            })
+            .map(|(_, size)| size)
+            .sum();
+        // rounding is internal fragmentation too, but it's
+        // worth knowing how much of it there is on its own.
+        let total_rounding: Size = info
+            .iter()
+            .filter(|(process_or_free, _)| matches!(process_or_free, ProcessOrFree::Rounding(..)))
+            .map(|(_, size)| size)
+            .sum();
+        let rounding = if total_rounding == 0 {
+            String::new()
+        } else {
+            format!(", Rounding: {}", format_size(total_rounding))
+        };
+        let total_full: Size = info.iter().map(|(_, size)| size).sum();
         let percentage = total_free * 100 / total_full;
+        let slabs = if slabs.is_empty() {
+            String::new()
+        } else {
+            format!(", {}", Self::slab_stats(&slabs))
+        };
+        let ends = frame
+            .ends()
+            .map(|ends| format!(", {}", Self::end_stats(ends)))
+            .unwrap_or_default();
+        let classes = frame.classes();
+        let classes = if classes.is_empty() {
+            String::new()
+        } else {
+            format!(", {}", Self::class_stats(&classes))
+        };
+        let searched = frame.searched();
+        let searched = if searched.holes == 0 {
+            String::new()
+        } else {
+            format!(
+                ", Holes Searched: {} (at most {} for one request)",
+                searched.holes, searched.longest
+            )
+        };
+        let moved = if moved == 0 {
+            String::new()
+        } else {
+            format!(", Compaction Moved: {}", format_size(moved))
+        };
+        // how much of the process in flight has been copied so far.
+        let moving = moving
+            .and_then(|(addr, copied)| {
+                let mut start = 0;
+                info.iter()
+                    .find_map(|(_, size)| {
+                        start += size;
+                        (addr < start).then_some(*size)
+                    })
+                    .map(|size| format!(", Moving: {}/{}", format_size(copied), format_size(size)))
+            })
+            .unwrap_or_default();
         format!(
-            "Total Free: {total_free}, Percentage Free: {percentage}, Hole(s): {num_holes}\nREMAINING REQUESTS: [{}]",
+            "Total Free: {}, Percentage Free: {percentage}, Hole(s): {num_holes}, Internal Fragmentation: {}{rounding}{ends}{slabs}{classes}{searched}{moved}{moving}\nREMAINING REQUESTS: [{}]",
+            format_size(total_free),
+            format_size(total_padding),
             requests
                 .iter()
                 .map(|req| {
                     format!(
-                        "P{pid}[{lifetime}s]({size}KB)",
+                        "P{pid}[{lifetime}s]({size})",
                         pid = req.process.0,
                         lifetime = req.lifetime,
-                        size = req.size
+                        size = format_size(req.size)
                     )
                 })
                 .collect::<Vec<_>>()
                 .join("|")
         )
     }
-    fn draw_ram(info: &[(ProcessOrFree, u32)]) -> String {
+    /// draws memory, with a `>` in front of the region the rover's in, if
+    /// there's one. A rover at the end of memory starts again from the start.
+    fn draw_ram(
+        info: &[(ProcessOrFree, Size)],
+        rover: Option<Addr>,
+        moving: Option<Addr>,
+    ) -> String {
+        let total: Size = info.iter().map(|(_, size)| size).sum();
+        let rover = rover.map(|rover| if rover >= total { 0 } else { rover });
         let mut out = String::new();
-        for (proc_or_free, size) in info {
+        let mut addr = 0;
+        let mut info = info.iter().peekable();
+        while let Some((proc_or_free, size)) = info.next() {
+            let start = addr;
+            addr += size;
+            // merging in the rounding after a process below moves `addr`
+            // along too, so check where the cell ends after it's drawn.
+            let cell = out.len();
             match proc_or_free {
                 ProcessOrFree::Process(pid, lifetime) => {
-                    out += &format!("p{pid}[{lifetime}s]({size}KB)|", pid = pid.0)
+                    // rounding right after a process is what it was given on top of what it
+                    // asked for, so show both, e.g. p1[3s](30KB/32KB).
+                    let granted = match info.peek() {
+                        Some((ProcessOrFree::Rounding(owner, expiry), waste))
+                            if (owner, expiry) == (pid, lifetime) =>
+                        {
+                            info.next();
+                            addr += waste;
+                            format!("/{}", format_size(size + waste))
+                        }
+                        _ => String::new(),
+                    };
+                    out += &format!(
+                        "p{pid}[{lifetime}s]({}{granted})|",
+                        format_size(*size),
+                        pid = pid.0
+                    )
+                }
+                ProcessOrFree::Padding(..) => out += &format!("pad({})|", format_size(*size)),
+                ProcessOrFree::Rounding(..) => out += &format!("round({})|", format_size(*size)),
+                ProcessOrFree::Slab(object_size) => {
+                    out += &format!(
+                        "slab<{}>({})|",
+                        format_size(*object_size),
+                        format_size(*size)
+                    )
                 }
-                ProcessOrFree::Free => out += &format!("FREE({size}KB)|"),
+                ProcessOrFree::Free => out += &format!("FREE({})|", format_size(*size)),
+            }
+            if moving.is_some_and(|moving| (start..addr).contains(&moving)) {
+                out.insert(cell, '~');
+            }
+            if rover.is_some_and(|rover| (start..addr).contains(&rover)) {
+                out.insert(cell, '>');
             }
         }
         out
     }
-    fn draw_gui(&mut self, config: Config) {
-        println!("Do you want auto mode? y/n");
-        let mut buff = String::new();
-        std::io::stdin().read_line(&mut buff).unwrap();
-        if buff.trim().to_lowercase() == "y" {
-            println!("Screen will update every 2 seconds.");
+    fn draw_gui(&mut self, playback: Playback) {
+        if let Playback::Auto(interval) = playback {
+            println!("Screen will update every {interval:?}.");
         }
-        for i in 0..(*[
-            self.frame_info[0].len(),
-            self.frame_info[1].len(),
-            self.frame_info[2].len(),
-        ]
-        .iter()
-        .min()
-        .unwrap())
-        {
+        let num_frames = self
+            .frame_info
+            .iter()
+            .map(|(_, frames)| frames.len())
+            .min()
+            .unwrap_or(0);
+        for i in 0..num_frames {
             println!("------------------------------------------------------");
-            println!("Best Fit:");
-            println!(
-                "[{}]",
-                Self::draw_ram(&Self::frames(&self.frame_info[1][i].0))
-            );
-            println!(
-                "{}",
-                Self::stats(
-                    &Self::frames(&self.frame_info[1][i].0),
-                    &self.frame_info[1][i].1
-                )
-            );
-            println!();
-            println!("Next Fit:");
-            println!(
-                "[{}]",
-                Self::draw_ram(&Self::frames(&self.frame_info[0][i].0))
-            );
-            println!(
-                "{}",
-                Self::stats(
-                    &Self::frames(&self.frame_info[0][i].0),
-                    &self.frame_info[0][i].1
-                )
-            );
-            println!();
-            println!("Worst Fit:");
-            println!(
-                "[{}]",
-                Self::draw_ram(&Self::frames(&self.frame_info[2][i].0))
-            );
-            println!(
-                "{}",
-                Self::stats(
-                    &Self::frames(&self.frame_info[2][i].0),
-                    &self.frame_info[2][i].1
-                )
-            );
-            if buff.trim().to_lowercase() == "y" {
-                std::thread::sleep(Duration::from_secs(2));
-            } else {
-                println!("Please press enter to advance.");
-                buff.clear();
-                std::io::stdin().read_line(&mut buff).unwrap();
+            for (label, frames) in &self.frame_info {
+                let info = Self::frames(&frames[i].layout());
+                println!("{label}:");
+                println!(
+                    "[{}]",
+                    Self::draw_ram(
+                        &info,
+                        frames[i].rover(),
+                        frames[i].moving().map(|(addr, _)| addr)
+                    )
+                );
+                println!("{}", Self::stats(&info, &frames[i]));
+                println!();
+            }
+            match playback {
+                Playback::Auto(interval) => std::thread::sleep(interval),
+                Playback::Step => {
+                    println!("Please press enter to advance.");
+                    std::io::stdin().read_line(&mut String::new()).unwrap();
+                }
+                Playback::NonInteractive => {}
             }
         }
     }
+
+    /// A one line summary of the whole run of a strategy.
+    fn summary(label: &str, frames: &Frames) -> String {
+        let ticks = frames.len().max(1) as u64;
+        let (free, holes, padding, rounding, waiting, occupancy, slab_ticks) =
+            frames.iter().fold((0, 0, 0, 0, 0, 0, 0), |acc, frame| {
+                let info = Self::frames(&frame.layout());
+                let total: Size = info.iter().map(|(_, size)| size).sum();
+                let (free, holes) = info
+                    .iter()
+                    .filter(|(process_or_free, _)| *process_or_free == ProcessOrFree::Free)
+                    .fold((0, 0), |(free, holes), (_, size)| (free + size, holes + 1));
+                let (padding, rounding) =
+                    info.iter()
+                        .fold((0, 0), |(padding, rounding), (process_or_free, size)| {
+                            match process_or_free {
+                                ProcessOrFree::Padding(..) => (padding + size, rounding),
+                                ProcessOrFree::Rounding(..) => (padding + size, rounding + size),
+                                _ => (padding, rounding),
+                            }
+                        });
+                let slabs = frame.slabs();
+                let used: u32 = slabs.iter().map(|slab| slab.used).sum();
+                let slots: u32 = slabs.iter().map(|slab| slab.slots).sum();
+                (
+                    acc.0 + free * 100 / total.max(1),
+                    acc.1 + holes,
+                    acc.2 + padding * 100 / total.max(1),
+                    acc.3 + rounding * 100 / total.max(1),
+                    acc.4.max(frame.requests().len()),
+                    acc.5 + used * 100 / slots.max(1),
+                    acc.6 + u32::from(!slabs.is_empty()),
+                )
+            });
+        // only counting the ticks that had any slabs.
+        let slabs = occupancy
+            .checked_div(slab_ticks)
+            .map(|occupancy| format!("  avg slab occupancy: {occupancy:>3}%"))
+            .unwrap_or_default();
+        // only worth showing if there's an allocation unit to round up to.
+        let rounding = if rounding == 0 {
+            String::new()
+        } else {
+            format!("  avg rounding: {:>3}%", rounding / ticks)
+        };
+        // only counting the ticks that had their ends kept apart.
+        let (low, high, end_ticks) = frames.iter().filter_map(|frame| frame.ends()).fold(
+            (0, 0, 0),
+            |(low, high, ticks), (low_end, high_end)| {
+                (low + low_end.holes, high + high_end.holes, ticks + 1)
+            },
+        );
+        let ends = low
+            .checked_div(end_ticks)
+            .zip(high.checked_div(end_ticks))
+            .map(|(low, high)| format!("  avg low/high end hole(s): {low}/{high}"))
+            .unwrap_or_default();
+        // the counts add up over the run, so the last frame has them all.
+        let classes = frames
+            .last()
+            .map(|frame| frame.classes())
+            .unwrap_or_default();
+        let hits: u64 = classes.iter().map(|class| class.hits).sum();
+        let placed: u64 = classes.iter().map(|class| class.hits + class.misses).sum();
+        let classes = if placed == 0 {
+            String::new()
+        } else {
+            format!("  class hits: {hits}/{placed}")
+        };
+        let searched: u64 = frames.iter().map(|frame| frame.searched().holes).sum();
+        let longest = frames
+            .iter()
+            .map(|frame| frame.searched().longest)
+            .max()
+            .unwrap_or(0);
+        let searched = if searched == 0 {
+            String::new()
+        } else {
+            format!("  holes searched: {searched} (longest {longest})")
+        };
+        let moved: Size = frames.iter().map(|frame| frame.moved()).sum();
+        let moved = if moved == 0 {
+            String::new()
+        } else {
+            format!("  moved: {}", format_size(moved))
+        };
+        format!(
+            "{label:<16} ticks: {:>5}  avg free: {:>3}%  avg hole(s): {:>3}  avg internal: {:>3}%{rounding}  peak waiting: {waiting}{ends}{slabs}{classes}{searched}{moved}",
+            frames.len(),
+            free / ticks,
+            holes / ticks,
+            padding / ticks,
+        )
+    }
 }
 
-pub(crate) fn draw_gui(
-    frame_info: [Vec<(Vec<MemoryRegion>, Vec<MemoryRequest>)>; 3],
-    config: Config,
-) {
-    Gui::new(frame_info).draw_gui(config);
+pub(crate) fn draw_gui(frame_info: Vec<(String, Frames)>, playback: Playback) {
+    Gui::new(frame_info).draw_gui(playback);
+}
+
+/// Prints a summary of each strategy's run, used by batch mode.
+pub(crate) fn print_summary(frame_info: &[(String, Frames)]) {
+    for (label, frames) in frame_info {
+        println!("{}", Gui::summary(label, frames));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_granted() {
+        let info = Gui::frames(&[
+            MemoryRegion(Owner::Process(Pid(1), 3), 0),
+            MemoryRegion(Owner::Rounding(Pid(1), 3), 30 * 1024),
+            MemoryRegion(Owner::Padding(Pid(2), 3), 32 * 1024),
+            MemoryRegion(Owner::Process(Pid(2), 3), 36 * 1024),
+            MemoryRegion(Owner::Free, 40 * 1024),
+            MemoryRegion(Owner::End, 64 * 1024),
+        ]);
+        assert_eq!(
+            Gui::draw_ram(&info, None, None),
+            "p1[3s](30KB/32KB)|pad(4KB)|p2[3s](4KB)|FREE(24KB)|"
+        );
+        // padding after a process isn't part of what it was rounded up to.
+        let padded = Gui::frames(&[
+            MemoryRegion(Owner::Process(Pid(1), 3), 0),
+            MemoryRegion(Owner::Padding(Pid(1), 3), 30 * 1024),
+            MemoryRegion(Owner::End, 32 * 1024),
+        ]);
+        assert_eq!(Gui::draw_ram(&padded, None, None), "p1[3s](30KB)|pad(2KB)|");
+        // anywhere in the process or its padding is the same cell.
+        assert_eq!(
+            Gui::draw_ram(&info, Some(31 * 1024), None),
+            ">p1[3s](30KB/32KB)|pad(4KB)|p2[3s](4KB)|FREE(24KB)|"
+        );
+        // and the process that's being moved gets a `~`.
+        assert_eq!(
+            Gui::draw_ram(&info, None, Some(36 * 1024)),
+            "p1[3s](30KB/32KB)|pad(4KB)|~p2[3s](4KB)|FREE(24KB)|"
+        );
+    }
 }
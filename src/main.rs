@@ -1,78 +1,179 @@
-use std::{fs, ops::Deref};
+use std::fs;
 
+mod cli;
 mod log;
+mod record;
 
+use cli::{parse_args, Args, Command, USAGE};
 use contiguous_memory_allocation::{
-    parse_config,
-    strategies::{BestFit, MemAllocator, MemoryRegion, MemoryRequest, NextFit, Pid, WorstFit},
+    parse_config, parse_scenarios,
+    strategies::{self, AllocOptions, Frames, MemoryRequest, Pid, SlabOptions, TwoEndedOptions},
+    Config,
 };
 use rand::{thread_rng, Rng};
 
-use crate::log::draw_gui;
+use crate::log::{draw_gui, print_summary};
 
 fn main() {
-    // get the config file as the first argument to the process.
-    let mut args = std::env::args();
     // first arg is always executing process name
-    let _proc_name = args.next().unwrap();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+    let args = parse_args(args).unwrap_or_else(|err| exit_with(&err));
 
-    let file = std::path::PathBuf::from(
-        args.next()
-            .expect("Please pass in the filepath to the config"),
-    );
+    if args.command == Command::Replay {
+        let file = fs::read_to_string(&args.path).unwrap_or_else(|err| exit_with(&err.to_string()));
+        let run =
+            record::read_run(&file).unwrap_or_else(|| exit_with("Couldn't read the run to replay"));
+        draw_gui(run, args.playback);
+        return;
+    }
 
-    let config = parse_config(fs::read_to_string(file).unwrap().as_str()).unwrap();
-    println!("Loaded config: {:#?}", config);
-    let worst = WorstFit::new(config.memory_max);
-    let best = BestFit::new(config.memory_max);
-    let next = NextFit::new(config.memory_max);
-    let requests = gen_processes(config.num_proc, config.proc_size_max, config.max_proc_time);
-    let results = std::sync::Mutex::new([None, None, None]);
-    // we do this threaded bc I accidentally did a sleep, and I thought my simulation was just kind of slow...
-    // turns out no, it's actually fast - but I ended up having threaded it anyways to do it concurrently so
-    // here you go.
-    std::thread::scope(|s| {
-        s.spawn(|| results.lock().unwrap()[0] = Some(driver(next, &requests)));
-        s.spawn(|| results.lock().unwrap()[1] = Some(driver(best, &requests)));
-        s.spawn(|| results.lock().unwrap()[2] = Some(driver(worst, &requests)));
-    });
-    draw_gui(
-        results
-            .into_inner()
-            .unwrap()
-            .map(|i| i.unwrap())
-            .try_into()
-            .unwrap(),
-        config,
-    );
+    let scenarios = load_scenarios(&args);
+    match args.command {
+        Command::Run | Command::Compare => {
+            for (name, config) in scenarios {
+                println!("Running scenario: {name}");
+                println!("Loaded config: {:#?}", config);
+                let mut results = simulate(&config);
+                if args.command == Command::Run {
+                    // run only shows the first strategy.
+                    results.truncate(1);
+                }
+                draw_gui(results, args.playback);
+            }
+        }
+        Command::Batch => {
+            for (name, config) in scenarios {
+                println!("Scenario: {name}");
+                print_summary(&simulate(&config));
+                println!();
+            }
+        }
+        Command::Export => {
+            let [(_, config)] = &scenarios[..] else {
+                exit_with("export writes a single scenario, pick one with --scenario");
+            };
+            let out = args.out.unwrap();
+            fs::write(&out, record::write_run(&simulate(config))).unwrap();
+            println!("Wrote the run to {}", out.display());
+        }
+        Command::Replay => unreachable!(),
+    }
 }
 
-fn driver<T: MemAllocator>(
-    mut alloc: T,
-    requests: &[MemoryRequest],
-) -> Vec<(Vec<MemoryRegion>, Vec<MemoryRequest>)> {
-    for req in requests {
-        alloc = alloc.request(*req);
+fn exit_with(err: &str) -> ! {
+    eprintln!("{err}\n\n{USAGE}");
+    std::process::exit(1)
+}
+
+/// Reads the scenarios out of the config, keeping only the one
+/// asked for (if any) and applying the command line overrides.
+fn load_scenarios(args: &Args) -> Vec<(String, Config)> {
+    let file = fs::read_to_string(&args.path).unwrap_or_else(|err| exit_with(&err.to_string()));
+    let mut scenarios = parse_scenarios(&file).unwrap_or_else(|err| exit_with(&err.to_string()));
+    if scenarios.is_empty() {
+        let config = parse_config(&file).unwrap_or_else(|err| exit_with(&err.to_string()));
+        scenarios.push(("default".to_string(), config));
     }
-    let mut out = vec![];
-    loop {
-        let (mem, reqs, alloc_new) = alloc.tick();
-        alloc = alloc_new;
-        if mem.len() == 2 {
-            break;
+    if let Some(scenario) = &args.scenario {
+        scenarios.retain(|(name, _)| name == scenario);
+        if scenarios.is_empty() {
+            exit_with(&format!("No scenario named {scenario} in the config"));
         }
-        out.push((mem, reqs));
     }
-    return out;
+    for (_, config) in scenarios.iter_mut() {
+        for (key, value) in &args.overrides {
+            if let Err(err) = config.set(key, value) {
+                exit_with(&err.to_string());
+            }
+        }
+    }
+    scenarios
+}
+
+/// Runs the same randomly generated processes through every strategy
+/// in the config.
+fn simulate(config: &Config) -> Vec<(String, Frames)> {
+    let requests = gen_processes(
+        config.num_proc,
+        config.proc_size_max,
+        config.max_proc_time,
+        config.alignment,
+    );
+    // we do this threaded bc I accidentally did a sleep, and I thought my simulation was just kind of slow...
+    // turns out no, it's actually fast - but I ended up having threaded it anyways to do it concurrently so
+    // here you go.
+    std::thread::scope(|s| {
+        config
+            .strategies
+            .iter()
+            .filter_map(|name| strategies::by_name(name))
+            .map(|strategy| {
+                s.spawn(|| {
+                    let options = AllocOptions {
+                        min_fragment: config.min_fragment,
+                        unit: config.allocation_unit,
+                        partitions: config.partitions.clone(),
+                        queueing: config.partition_queues,
+                        slabs: SlabOptions {
+                            threshold: config.slab_threshold,
+                            size: config.slab_size,
+                            backing: config.slab_backing,
+                        },
+                        two_ended: TwoEndedOptions {
+                            threshold: config.two_ended_threshold,
+                            base: config.two_ended_base,
+                        },
+                        rover: config.rover_policy,
+                        free_list: config.free_list_order,
+                    };
+                    let alloc = (strategy.new)(config.memory_max, options);
+                    (
+                        alloc.name().to_string(),
+                        strategies::simulate(alloc, &requests, config.compaction),
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
 }
 
-fn gen_processes(num_processes: u32, max_size: u32, lifetime: u32) -> Vec<MemoryRequest> {
+fn gen_processes(
+    num_processes: u32,
+    max_size: u64,
+    lifetime: u32,
+    align: u64,
+) -> Vec<MemoryRequest> {
     let mut rng = thread_rng();
     (0..num_processes)
         .map(|i| MemoryRequest {
             process: Pid(i),
-            size: rng.gen_range(0..max_size),
-            lifetime: rng.gen_range(0..lifetime / 1000),
+            size: rng.gen_range(1..=max_size.max(1)),
+            // `lifetime` is in ms and a tick is a second, so anything
+            // under a second still lasts a tick.
+            lifetime: (rng.gen_range(0..lifetime.max(1)) / 1000).max(1),
+            align,
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::gen_processes;
+
+    #[test]
+    fn test_sub_second_lifetimes() {
+        for req in gen_processes(20, 16, 500, 1) {
+            assert_eq!(req.lifetime, 1);
+        }
+        for req in gen_processes(20, 16, 10_000, 1) {
+            assert!((1..10).contains(&req.lifetime));
+        }
+    }
+}
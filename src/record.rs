@@ -0,0 +1,333 @@
+//! Reading and writing finished runs, so `export` can save a
+//! run to disk and `replay` can step through it again later.
+//!
+//! The format is plain text. A `strategy <label>` line starts
+//! the frames for that strategy, and every line after it is a
+//! single frame: the memory regions, a `|`, then the requests
+//! still waiting to be served. Padding is `pad:pid:lifetime@addr`
+//! and rounding is `round:pid:lifetime@addr`.
+//!
+//! Anything else the frame has goes after another `|`:
+//! - `size:slots:used:waste` for each slab,
+//! - `rover@addr` for where the rover is,
+//! - `moved:n` for how much compaction copied,
+//! - `moving:copied@addr` for the process it's part way through moving,
+//! - `searched:holes:longest` for how many holes were looked at,
+//! - `class:size:hits:misses` for each size class,
+//! - `ends:lh:lf:hh:hf` for the holes (and free bytes) at the low and
+//!   high ends of memory.
+//!
+//! ```text
+//! strategy Best Fit
+//! 0:3@0 free@56 pad:4:2@60 4:2@64 round:4:2@66 end@1024 | 6:45:6:1 8:253:4:8
+//! strategy Slab
+//! 0:3@0 slab:16@16 free@64 end@1024 | | 16:3:1:0
+//! strategy Next Fit
+//! 0:3@0 free@56 end@1024 | | rover@56 searched:3:2
+//! strategy First Fit
+//! 0:3@0 free@56 end@1024 | 9:200:4:1 | moved:128
+//! 0:3@0 free@56 4:2@64 end@1024 | | moving:32@64
+//! strategy Two-Ended
+//! 0:3@0 free@56 7:5@896 free@960 8:5@992 end@1024 | | class:64:1:0 ends:0:0:1:32
+//! ```
+
+use contiguous_memory_allocation::strategies::{
+    ClassStats, EndStats, Frames, MemoryRegion, MemoryRequest, Owner, Pid, Search, SlabInfo,
+    Snapshot,
+};
+
+fn write_region(region: &MemoryRegion) -> String {
+    match region {
+        MemoryRegion(Owner::Process(pid, lifetime), addr) => {
+            format!("{}:{lifetime}@{addr}", pid.0)
+        }
+        MemoryRegion(Owner::Padding(pid, lifetime), addr) => {
+            format!("pad:{}:{lifetime}@{addr}", pid.0)
+        }
+        MemoryRegion(Owner::Rounding(pid, lifetime), addr) => {
+            format!("round:{}:{lifetime}@{addr}", pid.0)
+        }
+        MemoryRegion(Owner::Slab(object_size), addr) => format!("slab:{object_size}@{addr}"),
+        MemoryRegion(Owner::Free, addr) => format!("free@{addr}"),
+        MemoryRegion(Owner::End, addr) => format!("end@{addr}"),
+    }
+}
+
+fn read_region(s: &str) -> Option<MemoryRegion> {
+    let (owner, addr) = s.split_once('@')?;
+    let addr = addr.parse().ok()?;
+    match owner {
+        "free" => return Some(MemoryRegion(Owner::Free, addr)),
+        "end" => return Some(MemoryRegion(Owner::End, addr)),
+        _ => {}
+    }
+    if let Some(object_size) = owner.strip_prefix("slab:") {
+        return Some(MemoryRegion(Owner::Slab(object_size.parse().ok()?), addr));
+    }
+    if let Some(owner) = owner.strip_prefix("pad:") {
+        let (pid, lifetime) = owner.split_once(':')?;
+        return Some(MemoryRegion(
+            Owner::Padding(Pid(pid.parse().ok()?), lifetime.parse().ok()?),
+            addr,
+        ));
+    }
+    if let Some(owner) = owner.strip_prefix("round:") {
+        let (pid, lifetime) = owner.split_once(':')?;
+        return Some(MemoryRegion(
+            Owner::Rounding(Pid(pid.parse().ok()?), lifetime.parse().ok()?),
+            addr,
+        ));
+    }
+    let (pid, lifetime) = owner.split_once(':')?;
+    Some(MemoryRegion(
+        Owner::Process(Pid(pid.parse().ok()?), lifetime.parse().ok()?),
+        addr,
+    ))
+}
+
+fn write_request(req: &MemoryRequest) -> String {
+    format!(
+        "{}:{}:{}:{}",
+        req.process.0, req.size, req.lifetime, req.align
+    )
+}
+
+fn read_request(s: &str) -> Option<MemoryRequest> {
+    let [pid, size, lifetime, align]: [&str; 4] =
+        s.split(':').collect::<Vec<_>>().try_into().ok()?;
+    Some(MemoryRequest {
+        process: Pid(pid.parse().ok()?),
+        size: size.parse().ok()?,
+        lifetime: lifetime.parse().ok()?,
+        align: align.parse().ok()?,
+    })
+}
+
+fn write_slab(slab: &SlabInfo) -> String {
+    format!(
+        "{}:{}:{}:{}",
+        slab.object_size, slab.slots, slab.used, slab.waste
+    )
+}
+
+fn read_slab(s: &str) -> Option<SlabInfo> {
+    let [object_size, slots, used, waste]: [&str; 4] =
+        s.split(':').collect::<Vec<_>>().try_into().ok()?;
+    Some(SlabInfo {
+        object_size: object_size.parse().ok()?,
+        slots: slots.parse().ok()?,
+        used: used.parse().ok()?,
+        waste: waste.parse().ok()?,
+    })
+}
+
+fn write_class(class: &ClassStats) -> String {
+    format!("class:{}:{}:{}", class.size, class.hits, class.misses)
+}
+
+fn read_class(s: &str) -> Option<ClassStats> {
+    let [size, hits, misses]: [&str; 3] = s.split(':').collect::<Vec<_>>().try_into().ok()?;
+    Some(ClassStats {
+        size: size.parse().ok()?,
+        hits: hits.parse().ok()?,
+        misses: misses.parse().ok()?,
+    })
+}
+
+fn write_ends((low, high): &(EndStats, EndStats)) -> String {
+    format!(
+        "ends:{}:{}:{}:{}",
+        low.holes, low.free, high.holes, high.free
+    )
+}
+
+fn read_ends(s: &str) -> Option<(EndStats, EndStats)> {
+    let [low_holes, low_free, high_holes, high_free]: [&str; 4] =
+        s.split(':').collect::<Vec<_>>().try_into().ok()?;
+    Some((
+        EndStats {
+            holes: low_holes.parse().ok()?,
+            free: low_free.parse().ok()?,
+        },
+        EndStats {
+            holes: high_holes.parse().ok()?,
+            free: high_free.parse().ok()?,
+        },
+    ))
+}
+
+/// Writes the frames of every strategy in the run.
+pub(crate) fn write_run(run: &[(String, Frames)]) -> String {
+    let mut out = String::new();
+    for (label, frames) in run {
+        out += &format!("strategy {label}\n");
+        for frame in frames {
+            let extras = frame
+                .slabs()
+                .iter()
+                .map(write_slab)
+                .chain(frame.rover().map(|rover| format!("rover@{rover}")))
+                .chain((frame.moved() > 0).then(|| format!("moved:{}", frame.moved())))
+                .chain(
+                    frame
+                        .moving()
+                        .map(|(addr, copied)| format!("moving:{copied}@{addr}")),
+                )
+                .chain((frame.searched().holes > 0).then(|| {
+                    let searched = frame.searched();
+                    format!("searched:{}:{}", searched.holes, searched.longest)
+                }))
+                .chain(frame.classes().iter().map(write_class))
+                .chain(frame.ends().as_ref().map(write_ends))
+                .collect::<Vec<_>>();
+            out += &format!(
+                "{} | {}{}\n",
+                frame
+                    .layout()
+                    .iter()
+                    .map(write_region)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                frame
+                    .requests()
+                    .iter()
+                    .map(write_request)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                if extras.is_empty() {
+                    String::new()
+                } else {
+                    format!(" | {}", extras.join(" "))
+                }
+            );
+        }
+    }
+    out
+}
+
+/// Reads a run written by [`write_run`].
+pub(crate) fn read_run(s: &str) -> Option<Vec<(String, Frames)>> {
+    let mut out: Vec<(String, Frames)> = vec![];
+    for line in s.lines().filter(|line| !line.trim().is_empty()) {
+        if let Some(label) = line.strip_prefix("strategy ") {
+            out.push((label.to_string(), vec![]));
+            continue;
+        }
+        let (mem, reqs) = line.split_once('|')?;
+        let (reqs, extras) = reqs.split_once('|').unwrap_or((reqs, ""));
+        let mem = mem
+            .split_whitespace()
+            .map(read_region)
+            .collect::<Option<Vec<_>>>()?;
+        let reqs = reqs
+            .split_whitespace()
+            .map(read_request)
+            .collect::<Option<Vec<_>>>()?;
+        let mut slabs = vec![];
+        let mut rover = None;
+        let mut moved = 0;
+        let mut moving = None;
+        let mut searched = Search::default();
+        let mut classes = vec![];
+        let mut ends = None;
+        for extra in extras.split_whitespace() {
+            if let Some(addr) = extra.strip_prefix("rover@") {
+                rover = Some(addr.parse().ok()?);
+            } else if let Some(moving_extra) = extra.strip_prefix("moving:") {
+                let (copied, addr) = moving_extra.split_once('@')?;
+                moving = Some((addr.parse().ok()?, copied.parse().ok()?));
+            } else if let Some(size) = extra.strip_prefix("moved:") {
+                moved = size.parse().ok()?;
+            } else if let Some(search) = extra.strip_prefix("searched:") {
+                let (holes, longest) = search.split_once(':')?;
+                searched = Search {
+                    holes: holes.parse().ok()?,
+                    longest: longest.parse().ok()?,
+                };
+            } else if let Some(class) = extra.strip_prefix("class:") {
+                classes.push(read_class(class)?);
+            } else if let Some(extra) = extra.strip_prefix("ends:") {
+                ends = Some(read_ends(extra)?);
+            } else {
+                slabs.push(read_slab(extra)?);
+            }
+        }
+        out.last_mut()?.1.push(
+            Snapshot::new(mem, reqs)
+                .with_slabs(slabs)
+                .with_rover(rover)
+                .with_moved(moved)
+                .with_moving(moving)
+                .with_searched(searched)
+                .with_classes(classes)
+                .with_ends(ends),
+        );
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let run = vec![(
+            "Best Fit".to_string(),
+            vec![
+                Snapshot::new(
+                    vec![
+                        MemoryRegion(Owner::Process(Pid(0), 3), 0),
+                        MemoryRegion(Owner::Free, 56),
+                        MemoryRegion(Owner::Padding(Pid(4), 2), 60),
+                        MemoryRegion(Owner::Process(Pid(4), 2), 64),
+                        MemoryRegion(Owner::Rounding(Pid(4), 2), 66),
+                        MemoryRegion(Owner::End, 1024),
+                    ],
+                    vec![MemoryRequest {
+                        process: Pid(6),
+                        size: 45,
+                        lifetime: 6,
+                        align: 1,
+                    }],
+                )
+                .with_searched(Search {
+                    holes: 3,
+                    longest: 2,
+                })
+                .with_classes(vec![ClassStats {
+                    size: 8,
+                    hits: 1,
+                    misses: 2,
+                }])
+                .with_ends(Some((
+                    EndStats { holes: 2, free: 12 },
+                    EndStats { holes: 1, free: 64 },
+                ))),
+                Snapshot::new(
+                    vec![MemoryRegion(Owner::Free, 0), MemoryRegion(Owner::End, 1024)],
+                    vec![],
+                )
+                .with_rover(Some(512))
+                .with_moved(128)
+                .with_moving(Some((64, 32))),
+                Snapshot::new(
+                    vec![
+                        MemoryRegion(Owner::Process(Pid(0), 3), 0),
+                        MemoryRegion(Owner::Slab(16), 16),
+                        MemoryRegion(Owner::Free, 64),
+                        MemoryRegion(Owner::End, 1024),
+                    ],
+                    vec![],
+                )
+                .with_slabs(vec![SlabInfo {
+                    object_size: 16,
+                    slots: 3,
+                    used: 1,
+                    waste: 0,
+                }]),
+            ],
+        )];
+        assert_eq!(read_run(&write_run(&run)), Some(run));
+    }
+}
@@ -1,39 +1,746 @@
 mod best_fit;
+mod first_fit;
+mod fit;
+mod fixed_partition;
+mod free_list;
 mod next_fit;
+mod score_fit;
+mod segregated_fit;
+mod slab;
+mod tlsf;
+mod two_ended;
 mod worst_fit;
 
-pub use best_fit::BestFit;
-pub use next_fit::NextFit;
-pub use worst_fit::WorstFit;
+pub use best_fit::{Best, BestFit};
+pub use first_fit::{First, FirstFit};
+pub use fit::{Backing, Fit, FitPolicy, Hole, Holes};
+pub use fixed_partition::{FixedPartition, Partitions, Queueing};
+pub use free_list::ListOrder;
+pub use next_fit::{NextFit, RoverPolicy};
+pub use score_fit::{ScoreFit, ScoreFn, Scored};
+pub use segregated_fit::{ClassStats, Segregated, SegregatedFit};
+pub use slab::{Slab, SlabInfo, SlabOptions};
+pub use tlsf::Tlsf;
+pub use two_ended::{EndStats, TwoEnded, TwoEndedOptions};
+pub use worst_fit::{Worst, WorstFit};
 
-type Addr = u32;
-type Lifetime = i32;
+use std::ops::Range;
+
+use im::Vector;
+
+/// An address in memory, in bytes.
+pub type Addr = u64;
+/// The size of something in memory, in bytes.
+pub type Size = u64;
+/// How long a process has left, or the tick it expires at. It's wider than
+/// a request's lifetime so adding one to the time can't overflow.
+type Lifetime = u64;
 
 #[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
 pub struct Pid(pub u32);
 
-/// This PID is special, it means that
-/// following address space isn't accessible (e.g.
-/// it's the final address in your address space).
-const FINAL_MEM_REGION_PID: u32 = 999;
+/// Who a memory region belongs to.
+#[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
+pub enum Owner {
+    /// nobody, it's a hole.
+    Free,
+    /// a process, and how long it has left.
+    Process(Pid, Lifetime),
+    /// memory a process was given but didn't ask for, e.g. to line
+    /// it up with its alignment. Goes away when the process does.
+    Padding(Pid, Lifetime),
+    /// memory a process was given because its request was rounded up
+    /// to a whole number of allocation units, see [`AllocOptions::unit`].
+    /// Like padding, it goes away when the process does.
+    Rounding(Pid, Lifetime),
+    /// part of a slab that no process is using, with the size of the
+    /// slab's objects. Only processes that small can use it, see [`Slab`].
+    Slab(Size),
+    /// the following address space isn't accessible (e.g.
+    /// it's the final address in your address space).
+    End,
+}
+
+impl Owner {
+    pub fn is_free(&self) -> bool {
+        *self == Owner::Free
+    }
+
+    pub fn is_process(&self) -> bool {
+        matches!(self, Owner::Process(..))
+    }
+
+    /// the process holding the region and when it lets go
+    /// of it, for processes and their padding.
+    fn held_by(&self) -> Option<(Pid, Lifetime)> {
+        match *self {
+            Owner::Process(pid, lifetime)
+            | Owner::Padding(pid, lifetime)
+            | Owner::Rounding(pid, lifetime) => Some((pid, lifetime)),
+            _ => None,
+        }
+    }
+}
 
 /// A Memory Region in the Memory.
-/// The first field represents a process that owns
-/// the region, if any. The second field represents
-/// where it starts.
+/// The first field represents who owns the region.
+/// The second field represents where it starts.
 /// In order to know where it ends, check the next
 /// memory regions start field (exclusive).
 #[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
-pub struct MemoryRegion(pub Option<(Pid, Lifetime)>, pub Addr);
+pub struct MemoryRegion(pub Owner, pub Addr);
 
 /// A Memory Request that needs to be served by the Memory
 /// allocator. It holds a PID that's requesting the memory,
-/// as well as the size it's requesting in KB.
+/// as well as the size it's requesting in bytes.
 #[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
 pub struct MemoryRequest {
     pub process: Pid,
-    pub size: u32,
+    pub size: Size,
     pub lifetime: u32,
+    /// the process has to start at a multiple of this,
+    /// which should be a power of two.
+    pub align: Size,
+}
+
+impl MemoryRequest {
+    /// the request, asking for at least a byte. A process that asks for
+    /// nothing still has to go somewhere, and a region with nothing in it
+    /// would start at the same address as the one after it.
+    fn at_least_a_byte(self) -> Self {
+        Self {
+            size: self.size.max(1),
+            ..self
+        }
+    }
+}
+
+/// A copy of an allocator's memory, along with the requests still waiting
+/// to be served, at some tick. These are backed by persistent collections,
+/// so keeping one around for every tick of a run is cheap: each one only
+/// really stores what changed since the last one.
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Snapshot {
+    // the lifetimes in here are the tick the process expires at, rather
+    // than how long it has left, otherwise every process would change
+    // every tick and there'd be nothing to share.
+    mem: Vector<MemoryRegion>,
+    reqs: Vector<MemoryRequest>,
+    time: u32,
+    /// how full each slab is, for allocators that have them.
+    slabs: Vector<SlabInfo>,
+    /// where [`NextFit`] starts its next search from.
+    rover: Option<Addr>,
+    /// how much memory compaction copied after the tick, see [`Compaction`].
+    moved: Size,
+    /// where the process [`Compaction::Incremental`] is part way through
+    /// moving starts, and how much of it's been copied so far.
+    moving: Option<(Addr, Size)>,
+    /// how much looking for holes the allocator did on the tick.
+    searched: Search,
+    /// how often each size class had a hole, for allocators that have them.
+    classes: Vector<ClassStats>,
+    /// the holes at the bottom and top of memory, see [`TwoEnded`].
+    ends: Option<(EndStats, EndStats)>,
+}
+
+impl Snapshot {
+    /// makes a snapshot that didn't come from an allocator,
+    /// e.g. one that was read from a file.
+    pub fn new(layout: Vec<MemoryRegion>, reqs: Vec<MemoryRequest>) -> Self {
+        Self {
+            mem: layout.into(),
+            reqs: reqs.into(),
+            time: 0,
+            slabs: Vector::new(),
+            rover: None,
+            moved: 0,
+            moving: None,
+            searched: Search::default(),
+            classes: Vector::new(),
+            ends: None,
+        }
+    }
+
+    pub fn with_rover(mut self, rover: Option<Addr>) -> Self {
+        self.rover = rover;
+        self
+    }
+
+    pub fn with_slabs(mut self, slabs: Vec<SlabInfo>) -> Self {
+        self.slabs = slabs.into();
+        self
+    }
+
+    pub fn with_moved(mut self, moved: Size) -> Self {
+        self.moved = moved;
+        self
+    }
+
+    pub fn with_moving(mut self, moving: Option<(Addr, Size)>) -> Self {
+        self.moving = moving;
+        self
+    }
+
+    pub fn with_searched(mut self, searched: Search) -> Self {
+        self.searched = searched;
+        self
+    }
+
+    pub fn with_classes(mut self, classes: Vec<ClassStats>) -> Self {
+        self.classes = classes.into();
+        self
+    }
+
+    pub fn with_ends(mut self, ends: Option<(EndStats, EndStats)>) -> Self {
+        self.ends = ends;
+        self
+    }
+
+    /// the state of memory, with how long each process has left.
+    pub fn layout(&self) -> Vec<MemoryRegion> {
+        self.mem
+            .iter()
+            .map(|region| time_left(*region, self.time))
+            .collect()
+    }
+
+    /// the requests that were still waiting.
+    pub fn requests(&self) -> Vec<MemoryRequest> {
+        self.reqs.iter().copied().collect()
+    }
+
+    /// how full each slab was, see [`Slab`].
+    pub fn slabs(&self) -> Vec<SlabInfo> {
+        self.slabs.iter().copied().collect()
+    }
+
+    /// where the allocator's rover was, if it has one, see [`NextFit`].
+    pub fn rover(&self) -> Option<Addr> {
+        self.rover
+    }
+
+    /// how many bytes compaction copied after the tick. For
+    /// [`Compaction::Incremental`] that's never more than its budget.
+    pub fn moved(&self) -> Size {
+        self.moved
+    }
+
+    /// the process part way through being moved, if there is one,
+    /// and how much of it's been copied so far.
+    pub fn moving(&self) -> Option<(Addr, Size)> {
+        self.moving
+    }
+
+    /// how many holes the allocator looked at to place requests on the
+    /// tick. Nothing for allocators that don't keep track.
+    pub fn searched(&self) -> Search {
+        self.searched
+    }
+
+    /// how often requests of each size class were served from their
+    /// own class so far, see [`SegregatedFit`].
+    pub fn classes(&self) -> Vec<ClassStats> {
+        self.classes.iter().copied().collect()
+    }
+
+    /// the holes among the small processes at the bottom of memory and
+    /// among the big ones at the top, for allocators that keep them apart.
+    pub fn ends(&self) -> Option<(EndStats, EndStats)> {
+        self.ends
+    }
+}
+
+/// How much looking for holes an allocator did to place requests on a tick,
+/// which is most of the cost of placing them. The longest search is what
+/// shows whether an allocator is bounded-time: TLSF never looks at more
+/// than one hole, first fit might have to look at all of them.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Search {
+    /// every hole looked at, for every request.
+    pub holes: u64,
+    /// the most holes a single request looked at.
+    pub longest: u64,
+}
+
+impl Search {
+    /// adds on a search that looked at this many holes.
+    fn record(&mut self, holes: u64) {
+        self.holes += holes;
+        self.longest = self.longest.max(holes);
+    }
+}
+
+/// the tick a process with the given lifetime, allocated at `time`, expires at.
+/// Allocators keep this in memory instead of the lifetime, see [`Snapshot`].
+fn expires_at(time: u32, lifetime: u32) -> Lifetime {
+    Lifetime::from(time) + Lifetime::from(lifetime)
+}
+
+/// turns a region holding the tick it expires at back into one
+/// holding how long it has left.
+fn time_left(region: MemoryRegion, time: u32) -> MemoryRegion {
+    let time = Lifetime::from(time);
+    match region {
+        MemoryRegion(Owner::Process(pid, expiry), addr) => {
+            MemoryRegion(Owner::Process(pid, expiry - time), addr)
+        }
+        MemoryRegion(Owner::Padding(pid, expiry), addr) => {
+            MemoryRegion(Owner::Padding(pid, expiry - time), addr)
+        }
+        MemoryRegion(Owner::Rounding(pid, expiry), addr) => {
+            MemoryRegion(Owner::Rounding(pid, expiry - time), addr)
+        }
+        _ => region,
+    }
+}
+
+/// how much padding the request needs at the start of a hole to
+/// be aligned, or `None` if it doesn't fit in the hole with it.
+fn padding_in(req: &MemoryRequest, addr: Addr, size: Size) -> Option<Size> {
+    let pad = addr.next_multiple_of(req.align.max(1)) - addr;
+    (pad + req.size <= size).then_some(pad)
+}
+
+/// Settings for how allocators carve up memory, the same for every strategy.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct AllocOptions {
+    /// if placing a process would leave a hole smaller than this, the
+    /// process gets the whole hole instead and the rest is wasted.
+    pub min_fragment: Size,
+    /// every process is given a whole number of these, e.g. 4KB pages.
+    /// 0 or 1 gives processes exactly what they ask for.
+    pub unit: Size,
+    /// how [`FixedPartition`] cuts up memory.
+    pub partitions: Partitions,
+    /// how processes wait for a [`FixedPartition`].
+    pub queueing: Queueing,
+    /// which requests [`Slab`] serves from slabs.
+    pub slabs: SlabOptions,
+    /// which requests [`TwoEnded`] puts at the top of memory.
+    pub two_ended: TwoEndedOptions,
+    /// where [`NextFit`]'s rover goes after placing a process.
+    pub rover: RoverPolicy,
+    /// the order first and next fit look through the holes in.
+    pub free_list: ListOrder,
+}
+
+impl AllocOptions {
+    /// whether what's left over of a hole is worth keeping as a hole.
+    fn keeps(&self, leftover: Size) -> bool {
+        leftover > 0 && leftover >= self.min_fragment
+    }
+
+    /// how much memory a process asking for `size` actually gets.
+    fn granted(&self, size: Size) -> Size {
+        size.next_multiple_of(self.unit.max(1))
+    }
+
+    /// the request, asking for as much as it'll actually get,
+    /// which is what has to fit in a hole.
+    fn rounded(&self, req: &MemoryRequest) -> MemoryRequest {
+        MemoryRequest {
+            size: self.granted(req.size),
+            ..*req
+        }
+    }
+}
+
+/// Gives the request the start of the hole at `index`, after `pad` bytes
+/// of padding, and shrinks the hole to whatever's left. What the process
+/// is given from rounding it up goes right after it, then a leftover that's
+/// not worth keeping goes after that as padding.
+/// Returns the index of the process's region.
+fn allocate(
+    mem: &mut Vector<MemoryRegion>,
+    index: usize,
+    pad: Size,
+    req: &MemoryRequest,
+    expiry: Lifetime,
+    options: &AllocOptions,
+) -> usize {
+    let addr = mem[index].1;
+    let start = addr + pad;
+    let granted = options.granted(req.size);
+    let mut leftover = mem[index + 1].1 - start - granted;
+    let mut waste = 0;
+    if !options.keeps(leftover) {
+        waste = leftover;
+        leftover = 0;
+    }
+    let regions = [
+        (pad > 0).then_some(MemoryRegion(Owner::Padding(req.process, expiry), addr)),
+        Some(MemoryRegion(Owner::Process(req.process, expiry), start)),
+        (granted > req.size).then_some(MemoryRegion(
+            Owner::Rounding(req.process, expiry),
+            start + req.size,
+        )),
+        (waste > 0).then_some(MemoryRegion(
+            Owner::Padding(req.process, expiry),
+            start + granted,
+        )),
+        (leftover > 0).then_some(MemoryRegion(Owner::Free, start + granted)),
+    ];
+    mem.remove(index);
+    for region in regions.into_iter().flatten().rev() {
+        mem.insert(index, region);
+    }
+    index + usize::from(pad > 0)
+}
+
+/// Goes through the queue once, front to back, and takes out every request
+/// `place` manages to put somewhere. Everything else waits for the next tick
+/// in the same order. Only the requests that were placed are taken out, so
+/// if nothing fits the queue doesn't change and stays shared with older
+/// snapshots.
+fn serve_queue<T: Clone>(queue: Vector<T>, mut place: impl FnMut(&T) -> bool) -> Vector<T> {
+    let placed = queue
+        .iter()
+        .enumerate()
+        .filter(|(_, req)| place(req))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut queue = queue;
+    for i in placed.into_iter().rev() {
+        queue.remove(i);
+    }
+    queue
+}
+
+/// Frees every region held by a process `should_free` picks (given the
+/// process and the tick it expires at), and merges neighboring regions with
+/// the same owner by removing the second one. Only the regions that actually
+/// change are touched, so memory stays shared with older copies.
+fn free_where(mem: &mut Vector<MemoryRegion>, should_free: impl Fn(Pid, Lifetime) -> bool) {
+    let freed = mem
+        .iter()
+        .enumerate()
+        .filter(|(_, region)| matches!(region.0.held_by(), Some((pid, expiry)) if should_free(pid, expiry)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    for i in freed {
+        mem[i].0 = Owner::Free;
+    }
+    merge_neighbours(mem);
+}
+
+/// Merges neighboring regions with the same owner by removing the second one.
+fn merge_neighbours(mem: &mut Vector<MemoryRegion>) {
+    let mut i = 1;
+    while i < mem.len() {
+        // slabs are given back one at a time, so they keep their own regions.
+        if mem[i].0 == mem[i - 1].0 && !matches!(mem[i].0, Owner::Slab(_)) {
+            mem.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// where a request put at the top of a hole ending at `end` starts,
+/// i.e. as high as it can go while still being aligned.
+fn top_start(req: &MemoryRequest, end: Addr, options: &AllocOptions) -> Addr {
+    let align = req.align.max(1);
+    (end - options.granted(req.size)) / align * align
+}
+
+/// Like [`allocate`], but puts the process at the top of the hole at `index`,
+/// so what's left of the hole is below it. What it's given from rounding it
+/// up goes right after it, and anything between that and the end of the hole
+/// from lining it up is padding.
+/// Returns the index of the process's region.
+fn allocate_top(
+    mem: &mut Vector<MemoryRegion>,
+    index: usize,
+    req: &MemoryRequest,
+    expiry: Lifetime,
+    options: &AllocOptions,
+) -> usize {
+    let addr = mem[index].1;
+    let end = mem[index + 1].1;
+    let start = top_start(req, end, options);
+    let granted = options.granted(req.size);
+    let waste = end - start - granted;
+    let mut leftover = start - addr;
+    let mut pad = 0;
+    if !options.keeps(leftover) {
+        pad = leftover;
+        leftover = 0;
+    }
+    let regions = [
+        (leftover > 0).then_some(MemoryRegion(Owner::Free, addr)),
+        (pad > 0).then_some(MemoryRegion(Owner::Padding(req.process, expiry), addr)),
+        Some(MemoryRegion(Owner::Process(req.process, expiry), start)),
+        (granted > req.size).then_some(MemoryRegion(
+            Owner::Rounding(req.process, expiry),
+            start + req.size,
+        )),
+        (waste > 0).then_some(MemoryRegion(
+            Owner::Padding(req.process, expiry),
+            start + granted,
+        )),
+    ];
+    mem.remove(index);
+    for region in regions.into_iter().flatten().rev() {
+        mem.insert(index, region);
+    }
+    index + usize::from(start > addr)
+}
+
+/// Slides every process down to the start of `mem`, keeping them in the
+/// same order, so all the free memory ends up in one hole at the end.
+/// Processes only ever move by a multiple of `align`, so as long as it's
+/// a power of two at least as big as any process's alignment they all
+/// stay aligned. The padding in front of them is worked out again from
+/// scratch, but padding (and rounding) after a process moves along with it.
+fn compacted(mem: &Vector<MemoryRegion>, align: Size) -> Vector<MemoryRegion> {
+    let end = *mem.last().expect("memory always has an end");
+    let mut out = Vector::new();
+    let mut addr = mem[0].1;
+    for (region, next) in mem.iter().zip(mem.iter().skip(1)) {
+        match region.0 {
+            Owner::Process(pid, expiry) => {
+                let start = addr + (region.1 - addr) % align;
+                if start > addr {
+                    out.push_back(MemoryRegion(Owner::Padding(pid, expiry), addr));
+                }
+                out.push_back(MemoryRegion(region.0, start));
+                addr = start + next.1 - region.1;
+            }
+            // padding right before its process lines it up, so it goes.
+            Owner::Padding(pid, expiry) if next.0 == Owner::Process(pid, expiry) => {}
+            // anything else is left over after the process before it.
+            Owner::Padding(..) | Owner::Rounding(..) => {
+                out.push_back(MemoryRegion(region.0, addr));
+                addr += next.1 - region.1;
+            }
+            // slabs move like processes, but there's nobody
+            // to give the space in front of them to.
+            Owner::Slab(_) => {
+                let start = addr + (region.1 - addr) % align;
+                if start > addr {
+                    out.push_back(MemoryRegion(Owner::Free, addr));
+                }
+                out.push_back(MemoryRegion(region.0, start));
+                addr = start + next.1 - region.1;
+            }
+            _ => {}
+        }
+    }
+    if addr < end.1 {
+        out.push_back(MemoryRegion(Owner::Free, addr));
+    }
+    out.push_back(end);
+    out
+}
+
+/// Compacts just the regions in `range`, leaving the rest of memory where
+/// it is. `range.end` is the region right after the last one that moves.
+fn compacted_between(
+    mem: &Vector<MemoryRegion>,
+    range: Range<usize>,
+    align: Size,
+) -> Vector<MemoryRegion> {
+    let mut window = compacted(&mem.skip(range.start).take(range.len() + 1), align);
+    // that's the region after, which stays put.
+    window.pop_back();
+    let mut out = mem.take(range.start);
+    out.append(window);
+    out.append(mem.skip(range.end));
+    merge_neighbours(&mut out);
+    out
+}
+
+/// Slides the processes between some neighbouring holes together so the
+/// holes merge into one that `req` fits in, picking whichever holes mean
+/// moving the fewest bytes. Nothing outside of those holes moves. `None`
+/// if there's no holes that would be big enough, even with everything moved.
+fn partially_compacted(
+    mem: &Vector<MemoryRegion>,
+    align: Size,
+    req: &MemoryRequest,
+) -> Option<Vector<MemoryRegion>> {
+    let holes = mem
+        .iter()
+        .enumerate()
+        .filter(|(_, region)| region.0.is_free())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let fits = |mem: &Vector<MemoryRegion>| {
+        mem.iter().zip(mem.iter().skip(1)).any(|(region, next)| {
+            region.0.is_free() && padding_in(req, region.1, next.1 - region.1).is_some()
+        })
+    };
+    let mut best: Option<(Size, Vector<MemoryRegion>)> = None;
+    for (i, first) in holes.iter().enumerate() {
+        let mut free = 0;
+        for last in &holes[i..] {
+            free += mem[last + 1].1 - mem[*last].1;
+            if free < req.size {
+                continue;
+            }
+            let out = compacted_between(mem, *first..last + 1, align);
+            if !fits(&out) {
+                // lining it up took some of it, so it needs another hole.
+                continue;
+            }
+            // taking in more holes only means moving more.
+            let moved = moved_bytes(mem, &out);
+            if best.as_ref().is_none_or(|(least, _)| moved < *least) {
+                best = Some((moved, out));
+            }
+            break;
+        }
+    }
+    best.map(|(_, out)| out)
+}
+
+/// the regions from a hole (at or after `from`) with something that can
+/// move down into it, to the end of that process (or slab) and its padding.
+fn next_slide(mem: &Vector<MemoryRegion>, from: usize) -> Option<Range<usize>> {
+    let hole = (from..mem.len() - 1)
+        .find(|&index| mem[index].0.is_free() && mem[index + 1].0 != Owner::End)?;
+    let mut end = hole + 1;
+    // padding in front of a process lines it up, so it goes with it.
+    if matches!(mem[end].0, Owner::Padding(..)) {
+        end += 1;
+    }
+    let owner = mem[end].0.held_by();
+    end += 1;
+    while owner.is_some() && mem[end].0.held_by() == owner {
+        end += 1;
+    }
+    Some(hole..end)
+}
+
+/// Slides processes (and slabs) down into the hole in front of them one at
+/// a time, lowest first, for as long as there's budget left to copy them.
+/// Given enough budget it ends up the same as [`compacted`]. Returns how
+/// much it moved, and where the next process to move starts if it ran out
+/// of budget for it.
+fn compacted_within(
+    mem: &Vector<MemoryRegion>,
+    align: Size,
+    budget: Size,
+) -> (Vector<MemoryRegion>, Size, Option<Addr>) {
+    let mut mem = mem.clone();
+    let mut moved = 0;
+    let mut from = 0;
+    while let Some(range) = next_slide(&mem, from) {
+        let out = compacted_between(&mem, range.clone(), align);
+        if out == mem {
+            // it's lined up as far down as it'll go already.
+            from = range.end;
+            continue;
+        }
+        let cost = moved_bytes(&mem, &out);
+        if moved + cost > budget {
+            let next = movable_in(&mem, range).map(|region| region.1);
+            return (mem, moved, next);
+        }
+        mem = out;
+        moved += cost;
+        from = range.start;
+    }
+    (mem, moved, None)
+}
+
+/// the process (or slab) [`compacted_within`] moves first, if anything
+/// needs moving at all.
+fn next_to_move(mem: &Vector<MemoryRegion>, align: Size) -> Option<MemoryRegion> {
+    let mut from = 0;
+    while let Some(range) = next_slide(mem, from) {
+        if compacted_between(mem, range.clone(), align) != *mem {
+            return movable_in(mem, range);
+        }
+        from = range.end;
+    }
+    None
+}
+
+/// the process (or slab) that slides down in `range`, see [`next_slide`].
+fn movable_in(mem: &Vector<MemoryRegion>, range: Range<usize>) -> Option<MemoryRegion> {
+    mem.iter()
+        .skip(range.start)
+        .take(range.len())
+        .find(|region| matches!(region.0, Owner::Process(..) | Owner::Slab(_)))
+        .copied()
+}
+
+/// how much memory had to be copied to get from `before` to `after`, i.e.
+/// the size of every process (and slab) that isn't where it was. They
+/// never change order, so they're just compared one by one.
+fn moved_bytes(before: &Vector<MemoryRegion>, after: &Vector<MemoryRegion>) -> Size {
+    let movable = |mem: &Vector<MemoryRegion>| {
+        mem.iter()
+            .zip(mem.iter().skip(1))
+            .filter(|(region, _)| matches!(region.0, Owner::Process(..) | Owner::Slab(_)))
+            .map(|(region, next)| (region.1, next.1 - region.1))
+            .collect::<Vec<_>>()
+    };
+    movable(before)
+        .into_iter()
+        .zip(movable(after))
+        .filter(|((from, _), (to, _))| from != to)
+        .map(|((_, size), _)| size)
+        .sum()
+}
+
+/// What to do when the request at the front of the queue doesn't fit
+/// anywhere, even though there might be enough free memory for it.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum Compaction {
+    /// nothing, it waits for something to be freed.
+    #[default]
+    Off,
+    /// slide every process down to the start of memory,
+    /// see [`MemAllocatorMut::compact`].
+    Full,
+    /// move as little as it takes to make a hole for it,
+    /// see [`MemAllocatorMut::compact_for`].
+    Partial,
+    /// don't wait for anything, just keep moving processes down a bit at a
+    /// time, copying at most this much a tick. A process that's too big to
+    /// move in one go is copied over a few ticks before it moves, see
+    /// [`MemAllocatorMut::compact_step`].
+    Incremental(Size),
+}
+
+/// The mutable version of [`MemAllocator`], which changes the allocator
+/// in place instead of returning a new one. When you don't need to keep
+/// old copies around (e.g. batch runs or benchmarks) this saves cloning
+/// the allocator on every operation.
+/// Every [`MemAllocatorMut`] that's [`Clone`] is a [`MemAllocator`] for free.
+pub trait MemAllocatorMut {
+    /// logs a memory request, to be served on the next tick.
+    fn request(&mut self, req: MemoryRequest);
+    /// advances the allocator by one tick, freeing processes
+    /// that expired and serving whatever requests it can.
+    fn tick(&mut self);
+    /// frees the memory of the process straight away,
+    /// regardless of how long it had left.
+    fn free(&mut self, pid: Pid);
+    /// slides every process down to the start of memory, leaving
+    /// a single hole at the end. Returns how many bytes it moved.
+    fn compact(&mut self) -> Size;
+    /// moves as few processes as it can so there's a hole the request fits
+    /// in, returning how many bytes it moved. `None` if it can't make one,
+    /// in which case nothing moves.
+    fn compact_for(&mut self, req: &MemoryRequest) -> Option<Size>;
+    /// moves processes down towards where [`compact`](Self::compact) would
+    /// put them, lowest first, as long as copying them fits in the budget.
+    /// Returns how many bytes it moved, and where the next process that
+    /// needs moving starts if there wasn't enough budget left for it.
+    fn compact_step(&mut self, budget: Size) -> (Size, Option<Addr>);
+    /// the process (or slab) [`compact_step`](Self::compact_step) would move
+    /// first, or `None` if everything's where [`compact`](Self::compact)
+    /// would put it. Its lifetime is the tick it expires at rather than how
+    /// long it has left, so it's the same from one tick to the next.
+    fn next_to_move(&self) -> Option<MemoryRegion>;
+    /// the name of the strategy to show people, e.g. `Best Fit`.
+    fn name(&self) -> &'static str;
+    /// a cheap copy of the current state of the allocator.
+    fn snapshot(&self) -> Snapshot;
 }
 
 /// This MemAllocator API is an immutable API. When working with
@@ -55,4 +762,903 @@ where
     /// the tick, as well as a copy of the inner working
     /// memory.
     fn tick(&self) -> (Vec<MemoryRegion>, Vec<MemoryRequest>, Self);
+    /// see [`MemAllocatorMut::compact`].
+    fn compact(&self) -> (Size, Self);
+    /// see [`MemAllocatorMut::compact_for`].
+    fn compact_for(&self, req: &MemoryRequest) -> (Option<Size>, Self);
+    /// see [`MemAllocatorMut::compact_step`].
+    fn compact_step(&self, budget: Size) -> (Size, Option<Addr>, Self);
+    /// see [`MemAllocatorMut::next_to_move`].
+    fn next_to_move(&self) -> Option<MemoryRegion>;
+    /// the name of the strategy to show people, e.g. `Best Fit`.
+    fn name(&self) -> &'static str;
+    /// a cheap copy of the current state of the allocator.
+    fn snapshot(&self) -> Snapshot;
+    /// the current state of memory.
+    fn layout(&self) -> Vec<MemoryRegion> {
+        self.snapshot().layout()
+    }
+}
+
+impl<T: MemAllocatorMut + Clone> MemAllocator for T {
+    fn request(&self, req: MemoryRequest) -> Self {
+        let mut out = self.clone();
+        MemAllocatorMut::request(&mut out, req);
+        out
+    }
+
+    fn tick(&self) -> (Vec<MemoryRegion>, Vec<MemoryRequest>, Self) {
+        let mut out = self.clone();
+        MemAllocatorMut::tick(&mut out);
+        let snapshot = MemAllocatorMut::snapshot(&out);
+        (snapshot.layout(), snapshot.requests(), out)
+    }
+
+    fn compact(&self) -> (Size, Self) {
+        let mut out = self.clone();
+        (MemAllocatorMut::compact(&mut out), out)
+    }
+
+    fn compact_for(&self, req: &MemoryRequest) -> (Option<Size>, Self) {
+        let mut out = self.clone();
+        (MemAllocatorMut::compact_for(&mut out, req), out)
+    }
+
+    fn compact_step(&self, budget: Size) -> (Size, Option<Addr>, Self) {
+        let mut out = self.clone();
+        let (moved, next) = MemAllocatorMut::compact_step(&mut out, budget);
+        (moved, next, out)
+    }
+
+    fn next_to_move(&self) -> Option<MemoryRegion> {
+        MemAllocatorMut::next_to_move(self)
+    }
+
+    fn name(&self) -> &'static str {
+        MemAllocatorMut::name(self)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        MemAllocatorMut::snapshot(self)
+    }
+}
+
+/// [`MemAllocator`] returns `Self`, which means you can't put
+/// different allocators in the same collection. This is the object
+/// safe version of it, so you can have a `Vec<Box<dyn DynMemAllocator>>`.
+/// Every [`MemAllocator`] is a [`DynMemAllocator`] for free.
+pub trait DynMemAllocator: Send + Sync {
+    /// the name of the strategy to show people, e.g. `Best Fit`.
+    fn name(&self) -> &'static str;
+    /// see [`MemAllocator::request`].
+    fn request(&self, req: MemoryRequest) -> Box<dyn DynMemAllocator>;
+    /// see [`MemAllocator::tick`].
+    fn tick(
+        &self,
+    ) -> (
+        Vec<MemoryRegion>,
+        Vec<MemoryRequest>,
+        Box<dyn DynMemAllocator>,
+    );
+    /// see [`MemAllocator::compact`].
+    fn compact(&self) -> (Size, Box<dyn DynMemAllocator>);
+    /// see [`MemAllocator::compact_for`].
+    fn compact_for(&self, req: &MemoryRequest) -> (Option<Size>, Box<dyn DynMemAllocator>);
+    /// see [`MemAllocator::compact_step`].
+    fn compact_step(&self, budget: Size) -> (Size, Option<Addr>, Box<dyn DynMemAllocator>);
+    /// see [`MemAllocator::next_to_move`].
+    fn next_to_move(&self) -> Option<MemoryRegion>;
+    /// see [`MemAllocator::snapshot`].
+    fn snapshot(&self) -> Snapshot;
+    /// the current state of memory.
+    fn layout(&self) -> Vec<MemoryRegion>;
+}
+
+impl<T: MemAllocator + Send + Sync + 'static> DynMemAllocator for T {
+    fn name(&self) -> &'static str {
+        MemAllocator::name(self)
+    }
+
+    fn request(&self, req: MemoryRequest) -> Box<dyn DynMemAllocator> {
+        Box::new(MemAllocator::request(self, req))
+    }
+
+    fn tick(
+        &self,
+    ) -> (
+        Vec<MemoryRegion>,
+        Vec<MemoryRequest>,
+        Box<dyn DynMemAllocator>,
+    ) {
+        let (mem, reqs, out) = MemAllocator::tick(self);
+        (mem, reqs, Box::new(out))
+    }
+
+    fn compact(&self) -> (Size, Box<dyn DynMemAllocator>) {
+        let (moved, out) = MemAllocator::compact(self);
+        (moved, Box::new(out))
+    }
+
+    fn compact_for(&self, req: &MemoryRequest) -> (Option<Size>, Box<dyn DynMemAllocator>) {
+        let (moved, out) = MemAllocator::compact_for(self, req);
+        (moved, Box::new(out))
+    }
+
+    fn compact_step(&self, budget: Size) -> (Size, Option<Addr>, Box<dyn DynMemAllocator>) {
+        let (moved, next, out) = MemAllocator::compact_step(self, budget);
+        (moved, next, Box::new(out))
+    }
+
+    fn next_to_move(&self) -> Option<MemoryRegion> {
+        MemAllocator::next_to_move(self)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        MemAllocator::snapshot(self)
+    }
+
+    fn layout(&self) -> Vec<MemoryRegion> {
+        MemAllocator::layout(self)
+    }
+}
+
+/// Every state of memory over a run, along with the requests that were
+/// still waiting at that point.
+pub type Frames = Vec<Snapshot>;
+
+/// Logs all the requests with the allocator, then ticks it until
+/// there's nothing left in memory, keeping a snapshot for every tick.
+/// Whenever the request at the front of the queue is still waiting after
+/// a tick, memory's compacted the way `compaction` says so it might fit
+/// on the next one. Incremental compaction doesn't wait, it goes a bit
+/// further every tick.
+pub fn simulate(
+    mut alloc: Box<dyn DynMemAllocator>,
+    requests: &[MemoryRequest],
+    compaction: Compaction,
+) -> Frames {
+    for req in requests {
+        alloc = alloc.request(*req);
+    }
+    let mut out = vec![];
+    // the process incremental compaction is part way through copying,
+    // and how much of it's been copied so far.
+    let mut copying = None;
+    loop {
+        let (mem, reqs, alloc_new) = alloc.tick();
+        alloc = alloc_new;
+        if !mem.iter().any(|region| region.0.is_process()) {
+            break;
+        }
+        let (moved, moving) = match (compaction, reqs.first()) {
+            (Compaction::Full, Some(req)) => {
+                let (moved, compacted) = alloc.compact();
+                // no point moving everything if it still won't fit, once
+                // it's been rounded up and lined up. With everything moved
+                // down there's a hole for it if the allocator can find one
+                // without moving anything else.
+                if compacted.compact_for(req).0 == Some(0) {
+                    alloc = compacted;
+                    (moved, None)
+                } else {
+                    (0, None)
+                }
+            }
+            (Compaction::Partial, Some(req)) => {
+                let (moved, alloc_new) = alloc.compact_for(req);
+                alloc = alloc_new;
+                (moved.unwrap_or(0), None)
+            }
+            (Compaction::Incremental(budget), _) => {
+                let (moved, moving, alloc_new) = compact_incrementally(alloc, budget, &mut copying);
+                alloc = alloc_new;
+                (moved, moving)
+            }
+            _ => (0, None),
+        };
+        out.push(alloc.snapshot().with_moved(moved).with_moving(moving));
+    }
+    out
+}
+
+/// Copies at most `budget` bytes towards compacting memory, see
+/// [`Compaction::Incremental`]. `copying` is the process it's part way
+/// through copying and how much of it's been copied, which only counts
+/// while that same process is still the next to move, e.g. not once it's
+/// gone and another one's been put where it was. Returns how much it copied,
+/// and where the process it's part way through starts and how much of it's
+/// been copied, if it didn't get all of it.
+fn compact_incrementally(
+    alloc: Box<dyn DynMemAllocator>,
+    budget: Size,
+    copying: &mut Option<(MemoryRegion, Size)>,
+) -> (Size, Option<(Addr, Size)>, Box<dyn DynMemAllocator>) {
+    let first = alloc.next_to_move();
+    let copied = copying
+        .filter(|(region, _)| Some(*region) == first)
+        .map_or(0, |(_, copied)| copied);
+    let (moved, next, alloc) = alloc.compact_step(copied + budget);
+    match first.filter(|region| next == Some(region.1)) {
+        // it's still not all there, so the whole budget went on it.
+        Some(region) => {
+            *copying = Some((region, copied + budget));
+            (budget, Some((region.1, copied + budget)), alloc)
+        }
+        // whatever's left over doesn't carry over to the next one.
+        None => {
+            *copying = None;
+            (moved - copied, None, alloc)
+        }
+    }
+}
+
+/// A strategy that can be picked by name, e.g. from the config.
+pub struct Strategy {
+    /// the short name used to pick it, e.g. `best`.
+    pub name: &'static str,
+    /// makes a new instance of the strategy with the given amount of memory.
+    pub new: fn(Size, AllocOptions) -> Box<dyn DynMemAllocator>,
+}
+
+/// All the strategies we know about. To add a new strategy,
+/// add it here and it can be used everywhere.
+pub const REGISTRY: &[Strategy] = &[
+    Strategy {
+        name: "best",
+        new: |mem_size, options| Box::new(BestFit::new(mem_size).with_options(options)),
+    },
+    Strategy {
+        name: "next",
+        new: |mem_size, options| Box::new(NextFit::new(mem_size).with_options(options)),
+    },
+    Strategy {
+        name: "worst",
+        new: |mem_size, options| Box::new(WorstFit::new(mem_size).with_options(options)),
+    },
+    Strategy {
+        name: "first",
+        new: |mem_size, options| Box::new(FirstFit::new(mem_size).with_options(options)),
+    },
+    Strategy {
+        name: "segregated",
+        new: |mem_size, options| Box::new(SegregatedFit::new(mem_size).with_options(options)),
+    },
+    Strategy {
+        name: "tlsf",
+        new: |mem_size, options| Box::new(Tlsf::new(mem_size).with_options(options)),
+    },
+    Strategy {
+        name: "slab",
+        new: slab::with_backing,
+    },
+    Strategy {
+        name: "two-ended",
+        new: two_ended::with_base,
+    },
+    Strategy {
+        name: "fixed",
+        new: |mem_size, options| Box::new(FixedPartition::with_options(mem_size, options)),
+    },
+];
+
+/// Looks up a strategy in the [`REGISTRY`] by its name.
+pub fn by_name(name: &str) -> Option<&'static Strategy> {
+    REGISTRY.iter().find(|strategy| strategy.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// every strategy that puts each process at the start of a hole, which
+    /// is everything but fixed partitions, slabs (small processes go in a
+    /// slab, and the slab goes in the hole) and two-ended (big processes go
+    /// at the end of the hole).
+    fn hole_fitters() -> impl Iterator<Item = &'static Strategy> {
+        REGISTRY
+            .iter()
+            .filter(|strategy| !["fixed", "slab", "two-ended"].contains(&strategy.name))
+    }
+
+    /// puts three processes in, frees the middle one and compacts.
+    fn free_then_compact(mut alloc: impl MemAllocatorMut) -> Vec<MemoryRegion> {
+        for (pid, size) in [(1, 10), (2, 20), (3, 30)] {
+            alloc.request(MemoryRequest {
+                process: Pid(pid),
+                size,
+                lifetime: 5,
+                align: 1,
+            });
+        }
+        alloc.tick();
+        alloc.free(Pid(2));
+        assert_eq!(alloc.snapshot().layout()[1], MemoryRegion(Owner::Free, 10));
+        alloc.compact();
+        alloc.snapshot().layout()
+    }
+
+    /// fills memory with one process, then queues up a million
+    /// more that can't fit. None of them should get lost or reordered.
+    fn queue_a_million(mut alloc: impl MemAllocatorMut) {
+        alloc.request(MemoryRequest {
+            process: Pid(0),
+            size: 1024,
+            lifetime: 5,
+            align: 1,
+        });
+        for pid in 1..=1_000_000 {
+            alloc.request(MemoryRequest {
+                process: Pid(pid),
+                size: 1,
+                lifetime: 5,
+                align: 1,
+            });
+        }
+        alloc.tick();
+        let reqs = alloc.snapshot().requests();
+        assert_eq!(reqs.len(), 1_000_000, "{}", alloc.name());
+        assert!(reqs.iter().map(|req| req.process.0).eq(1..=1_000_000));
+    }
+
+    #[test]
+    fn test_huge_queue() {
+        queue_a_million(BestFit::new(1024));
+        queue_a_million(WorstFit::new(1024));
+        queue_a_million(FirstFit::new(1024));
+        queue_a_million(NextFit::new(1024));
+    }
+
+    /// pids that used to mean the end of memory are just normal pids now.
+    #[test]
+    fn test_any_pid_is_valid() {
+        for pid in [Pid(999), Pid(u32::MAX)] {
+            let mut alloc = BestFit::new(64);
+            MemAllocatorMut::request(
+                &mut alloc,
+                MemoryRequest {
+                    process: pid,
+                    size: 64,
+                    lifetime: 5,
+                    align: 1,
+                },
+            );
+            MemAllocatorMut::tick(&mut alloc);
+            assert_eq!(
+                MemAllocatorMut::snapshot(&alloc).layout(),
+                vec![
+                    MemoryRegion(Owner::Process(pid, 5), 0),
+                    MemoryRegion(Owner::End, 64),
+                ]
+            );
+            alloc.free(pid);
+            assert_eq!(
+                MemAllocatorMut::snapshot(&alloc).layout(),
+                vec![MemoryRegion(Owner::Free, 0), MemoryRegion(Owner::End, 64)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_free_and_compact() {
+        let expected = vec![
+            MemoryRegion(Owner::Process(Pid(1), 5), 0),
+            MemoryRegion(Owner::Process(Pid(3), 5), 10),
+            MemoryRegion(Owner::Free, 40),
+            MemoryRegion(Owner::End, 128),
+        ];
+        assert_eq!(free_then_compact(BestFit::new(128)), expected);
+        assert_eq!(free_then_compact(NextFit::new(128)), expected);
+    }
+
+    #[test]
+    fn test_partial_compaction() {
+        for strategy in hole_fitters() {
+            let mut alloc = (strategy.new)(100, AllocOptions::default());
+            // the first and third expire, leaving holes of 5, 5 and 40.
+            for (pid, size, lifetime) in [(1, 5, 1), (2, 40, 5), (3, 5, 1), (4, 10, 5)] {
+                alloc = alloc.request(MemoryRequest {
+                    process: Pid(pid),
+                    size,
+                    lifetime,
+                    align: 1,
+                });
+            }
+            alloc = alloc.tick().2.tick().2;
+            let req = |size| MemoryRequest {
+                process: Pid(5),
+                size,
+                lifetime: 5,
+                align: 1,
+            };
+            // all of it moves...
+            assert_eq!(alloc.compact().0, 50, "{}", strategy.name);
+            // ...but only the last process has to for the last two holes to do.
+            let (moved, partial) = alloc.compact_for(&req(44));
+            assert_eq!(moved, Some(10), "{}", strategy.name);
+            assert_eq!(
+                partial.layout(),
+                vec![
+                    MemoryRegion(Owner::Free, 0),
+                    MemoryRegion(Owner::Process(Pid(2), 4), 5),
+                    MemoryRegion(Owner::Process(Pid(4), 4), 45),
+                    MemoryRegion(Owner::Free, 55),
+                    MemoryRegion(Owner::End, 100),
+                ],
+                "{}",
+                strategy.name
+            );
+            // there isn't that much free memory.
+            assert_eq!(alloc.compact_for(&req(51)).0, None, "{}", strategy.name);
+        }
+    }
+
+    #[test]
+    fn test_full_compaction_needs_enough_free() {
+        let requests =
+            [(1, 10, 1), (2, 10, 5), (3, 40, 5), (4, 60, 5)].map(|(pid, size, lifetime)| {
+                MemoryRequest {
+                    process: Pid(pid),
+                    size,
+                    lifetime,
+                    align: 1,
+                }
+            });
+        let frames = simulate(Box::new(BestFit::new(100)), &requests, Compaction::Full);
+        // once the first expires there's 50 free, which still isn't enough
+        // for the last one, so nothing gets moved for it.
+        assert_eq!(frames[1].layout()[0], MemoryRegion(Owner::Free, 0));
+        assert!(frames.iter().all(|frame| frame.moved() == 0));
+    }
+
+    #[test]
+    fn test_full_compaction_needs_enough_free_once_rounded() {
+        let options = AllocOptions {
+            unit: 16,
+            ..AllocOptions::default()
+        };
+        let requests =
+            [(1, 16, 1), (2, 16, 5), (3, 48, 5), (4, 33, 5)].map(|(pid, size, lifetime)| {
+                MemoryRequest {
+                    process: Pid(pid),
+                    size,
+                    lifetime,
+                    align: 1,
+                }
+            });
+        let frames = simulate(
+            Box::new(BestFit::new(100).with_options(options)),
+            &requests,
+            Compaction::Full,
+        );
+        // there's 36 free once the first expires, which is enough for the
+        // last one's 33 but not the 48 it gets rounded up to.
+        assert_eq!(frames[1].layout()[0], MemoryRegion(Owner::Free, 0));
+        assert!(frames.iter().all(|frame| frame.moved() == 0));
+    }
+
+    #[test]
+    fn test_incremental_compaction() {
+        let requests =
+            [(1, 5, 1), (2, 40, 5), (3, 5, 1), (4, 10, 5)].map(|(pid, size, lifetime)| {
+                MemoryRequest {
+                    process: Pid(pid),
+                    size,
+                    lifetime,
+                    align: 1,
+                }
+            });
+        let frames = simulate(
+            Box::new(BestFit::new(100)),
+            &requests,
+            Compaction::Incremental(30),
+        );
+        // nothing needs moving until the first and third expire, then
+        // the second takes two ticks to copy over.
+        assert_eq!(frames[0].moving(), None);
+        assert_eq!(frames[1].moved(), 30);
+        assert_eq!(frames[1].moving(), Some((5, 30)));
+        // it only has 10 left, which leaves enough for the last one to go
+        // straight after it.
+        assert_eq!(frames[2].moved(), 20);
+        assert_eq!(frames[2].moving(), None);
+        assert!(frames.iter().all(|frame| frame.moved() <= 30));
+        assert_eq!(
+            frames[2].layout(),
+            vec![
+                MemoryRegion(Owner::Process(Pid(2), 3), 0),
+                MemoryRegion(Owner::Process(Pid(4), 3), 40),
+                MemoryRegion(Owner::Free, 50),
+                MemoryRegion(Owner::End, 100),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_incremental_compaction_starts_over() {
+        let requests =
+            [(1, 5, 1), (2, 40, 2), (3, 10, 5)].map(|(pid, size, lifetime)| MemoryRequest {
+                process: Pid(pid),
+                size,
+                lifetime,
+                align: 1,
+            });
+        let frames = simulate(
+            Box::new(BestFit::new(100)),
+            &requests,
+            Compaction::Incremental(30),
+        );
+        assert_eq!(frames[1].moving(), Some((5, 30)));
+        // the second goes before it's copied over, so what was copied of
+        // it doesn't count towards the third.
+        assert_eq!(frames[2].moved(), 10);
+        assert_eq!(frames[2].moving(), None);
+    }
+
+    #[test]
+    fn test_incremental_compaction_knows_who_it_was_copying() {
+        let with = |pid, expiry| {
+            let mut alloc = BestFit::new(100);
+            alloc.set_layout(vec![
+                MemoryRegion(Owner::Free, 0),
+                MemoryRegion(Owner::Process(Pid(pid), expiry), 5),
+                MemoryRegion(Owner::Free, 45),
+                MemoryRegion(Owner::End, 100),
+            ]);
+            Box::new(alloc) as Box<dyn DynMemAllocator>
+        };
+        let mut copying = None;
+        let (moved, moving, _) = compact_incrementally(with(2, 10), 30, &mut copying);
+        assert_eq!((moved, moving), (30, Some((5, 30))));
+        // a different process is where the second was, so it starts over.
+        let (moved, moving, _) = compact_incrementally(with(4, 20), 30, &mut copying);
+        assert_eq!((moved, moving), (30, Some((5, 30))));
+        // the same one again carries on from where it got to.
+        let (moved, moving, alloc) = compact_incrementally(with(4, 20), 30, &mut copying);
+        assert_eq!((moved, moving), (10, None));
+        assert_eq!(alloc.next_to_move(), None);
+    }
+
+    #[test]
+    fn test_registry_is_uniform() {
+        // too big for a slab, so they all put it at the start.
+        let options = AllocOptions {
+            slabs: SlabOptions {
+                threshold: 8,
+                ..SlabOptions::default()
+            },
+            ..AllocOptions::default()
+        };
+        let allocs = REGISTRY
+            .iter()
+            .map(|strategy| (strategy.new)(64, options.clone()))
+            .collect::<Vec<Box<dyn DynMemAllocator>>>();
+        for alloc in allocs {
+            let (mem, _, alloc) = alloc
+                .request(MemoryRequest {
+                    process: Pid(1),
+                    size: 16,
+                    lifetime: 2,
+                    align: 1,
+                })
+                .tick();
+            assert_eq!(mem, alloc.layout());
+            assert_eq!(
+                mem[..2],
+                [
+                    MemoryRegion(Owner::Process(Pid(1), 2), 0),
+                    MemoryRegion(Owner::Free, 16)
+                ],
+                "{}",
+                alloc.name()
+            );
+        }
+    }
+
+    /// a lifetime that runs past the end of time doesn't overflow.
+    #[test]
+    fn test_huge_lifetime() {
+        let mut alloc = BestFit::new(64);
+        MemAllocatorMut::tick(&mut alloc);
+        MemAllocatorMut::request(
+            &mut alloc,
+            MemoryRequest {
+                process: Pid(1),
+                size: 64,
+                lifetime: u32::MAX,
+                align: 1,
+            },
+        );
+        MemAllocatorMut::tick(&mut alloc);
+        // it still has all of it left, however late it's placed.
+        assert_eq!(
+            MemAllocatorMut::snapshot(&alloc).layout()[0],
+            MemoryRegion(Owner::Process(Pid(1), u32::MAX.into()), 0)
+        );
+    }
+
+    #[test]
+    fn test_empty_requests_get_a_byte() {
+        for strategy in REGISTRY {
+            let mut alloc = (strategy.new)(64 * 1024, AllocOptions::default());
+            for (pid, size) in [(1, 0), (2, 0), (3, 5)] {
+                alloc = alloc.request(MemoryRequest {
+                    process: Pid(pid),
+                    size,
+                    lifetime: 2,
+                    align: 1,
+                });
+            }
+            let (mem, reqs, _) = alloc.tick();
+            assert!(reqs.is_empty(), "{}", strategy.name);
+            // every region has something in it, so none start at the same address.
+            assert!(
+                mem.windows(2).all(|pair| pair[0].1 < pair[1].1),
+                "{}: {mem:?}",
+                strategy.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_big_address_space() {
+        const GB: Size = 1024 * 1024 * 1024;
+        for strategy in hole_fitters() {
+            let (mem, _, _) = (strategy.new)(16 * GB, AllocOptions::default())
+                .request(MemoryRequest {
+                    process: Pid(1),
+                    size: 6 * GB + 1,
+                    lifetime: 2,
+                    align: 1,
+                })
+                .request(MemoryRequest {
+                    process: Pid(2),
+                    size: 9 * GB,
+                    lifetime: 2,
+                    align: 1,
+                })
+                .tick();
+            assert_eq!(
+                mem,
+                vec![
+                    MemoryRegion(Owner::Process(Pid(1), 2), 0),
+                    MemoryRegion(Owner::Process(Pid(2), 2), 6 * GB + 1),
+                    MemoryRegion(Owner::Free, 15 * GB + 1),
+                    MemoryRegion(Owner::End, 16 * GB),
+                ],
+                "{}",
+                strategy.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_alignment() {
+        for strategy in hole_fitters() {
+            let (mem, _, _) = [(1, 3, 1), (2, 8, 16), (3, 5, 1)]
+                .into_iter()
+                .fold(
+                    (strategy.new)(128, AllocOptions::default()),
+                    |alloc, (pid, size, align)| {
+                        alloc.request(MemoryRequest {
+                            process: Pid(pid),
+                            size,
+                            lifetime: 2,
+                            align,
+                        })
+                    },
+                )
+                .tick();
+            assert_eq!(
+                mem,
+                vec![
+                    MemoryRegion(Owner::Process(Pid(1), 2), 0),
+                    MemoryRegion(Owner::Padding(Pid(2), 2), 3),
+                    MemoryRegion(Owner::Process(Pid(2), 2), 16),
+                    MemoryRegion(Owner::Process(Pid(3), 2), 24),
+                    MemoryRegion(Owner::Free, 29),
+                    MemoryRegion(Owner::End, 128),
+                ],
+                "{}",
+                strategy.name
+            );
+        }
+    }
+
+    /// compacting moves things, but nothing should end up out of line.
+    fn compact_aligned(mut alloc: impl MemAllocatorMut) -> Vec<MemoryRegion> {
+        for (pid, size, align) in [(1, 3, 1), (2, 8, 16), (3, 5, 1)] {
+            alloc.request(MemoryRequest {
+                process: Pid(pid),
+                size,
+                lifetime: 2,
+                align,
+            });
+        }
+        alloc.tick();
+        alloc.free(Pid(1));
+        alloc.compact();
+        alloc.snapshot().layout()
+    }
+
+    #[test]
+    fn test_compaction_keeps_leftovers() {
+        let mut alloc = BestFit::new(128).with_options(AllocOptions {
+            min_fragment: 8,
+            ..AllocOptions::default()
+        });
+        for (pid, size) in [(1, 100), (2, 25)] {
+            MemAllocatorMut::request(
+                &mut alloc,
+                MemoryRequest {
+                    process: Pid(pid),
+                    size,
+                    lifetime: 2,
+                    align: 1,
+                },
+            );
+        }
+        MemAllocatorMut::tick(&mut alloc);
+        alloc.free(Pid(1));
+        MemAllocatorMut::compact(&mut alloc);
+        assert_eq!(
+            MemAllocatorMut::snapshot(&alloc).layout(),
+            vec![
+                MemoryRegion(Owner::Process(Pid(2), 2), 0),
+                MemoryRegion(Owner::Padding(Pid(2), 2), 25),
+                MemoryRegion(Owner::Free, 28),
+                MemoryRegion(Owner::End, 128),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compaction_keeps_alignment() {
+        let expected = vec![
+            MemoryRegion(Owner::Process(Pid(2), 2), 0),
+            MemoryRegion(Owner::Process(Pid(3), 2), 8),
+            MemoryRegion(Owner::Free, 13),
+            MemoryRegion(Owner::End, 128),
+        ];
+        assert_eq!(compact_aligned(BestFit::new(128)), expected);
+        assert_eq!(compact_aligned(NextFit::new(128)), expected);
+    }
+
+    #[test]
+    fn test_min_fragment() {
+        let options = AllocOptions {
+            min_fragment: 8,
+            ..AllocOptions::default()
+        };
+        for strategy in hole_fitters() {
+            let (mem, _, _) = [(1, 120), (2, 5)]
+                .into_iter()
+                .fold(
+                    (strategy.new)(128, options.clone()),
+                    |alloc, (pid, size)| {
+                        alloc.request(MemoryRequest {
+                            process: Pid(pid),
+                            size,
+                            lifetime: 2,
+                            align: 1,
+                        })
+                    },
+                )
+                .tick();
+            assert_eq!(
+                mem,
+                vec![
+                    MemoryRegion(Owner::Process(Pid(1), 2), 0),
+                    // the 3 left over isn't worth keeping around.
+                    MemoryRegion(Owner::Process(Pid(2), 2), 120),
+                    MemoryRegion(Owner::Padding(Pid(2), 2), 125),
+                    MemoryRegion(Owner::End, 128),
+                ],
+                "{}",
+                strategy.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_allocation_unit() {
+        let options = AllocOptions {
+            unit: 32,
+            ..AllocOptions::default()
+        };
+        for strategy in hole_fitters() {
+            let (mem, _, _) = [(1, 30), (2, 32), (3, 1)]
+                .into_iter()
+                .fold(
+                    (strategy.new)(128, options.clone()),
+                    |alloc, (pid, size)| {
+                        alloc.request(MemoryRequest {
+                            process: Pid(pid),
+                            size,
+                            lifetime: 2,
+                            align: 1,
+                        })
+                    },
+                )
+                .tick();
+            assert_eq!(
+                mem,
+                vec![
+                    MemoryRegion(Owner::Process(Pid(1), 2), 0),
+                    MemoryRegion(Owner::Rounding(Pid(1), 2), 30),
+                    MemoryRegion(Owner::Process(Pid(2), 2), 32),
+                    MemoryRegion(Owner::Process(Pid(3), 2), 64),
+                    MemoryRegion(Owner::Rounding(Pid(3), 2), 65),
+                    MemoryRegion(Owner::Free, 96),
+                    MemoryRegion(Owner::End, 128),
+                ],
+                "{}",
+                strategy.name
+            );
+        }
+    }
+
+    /// rounding and a leftover that's too small to keep are both
+    /// internal fragmentation, but they're kept apart.
+    #[test]
+    fn test_rounding_then_leftover() {
+        let options = AllocOptions {
+            unit: 32,
+            min_fragment: 8,
+            ..AllocOptions::default()
+        };
+        for strategy in hole_fitters() {
+            let (mem, _, _) = (strategy.new)(100, options.clone())
+                .request(MemoryRequest {
+                    process: Pid(1),
+                    size: 90,
+                    lifetime: 2,
+                    align: 1,
+                })
+                .tick();
+            assert_eq!(
+                mem,
+                vec![
+                    MemoryRegion(Owner::Process(Pid(1), 2), 0),
+                    MemoryRegion(Owner::Rounding(Pid(1), 2), 90),
+                    MemoryRegion(Owner::Padding(Pid(1), 2), 96),
+                    MemoryRegion(Owner::End, 100),
+                ],
+                "{}",
+                strategy.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_snapshots_share_memory() {
+        // enough processes that memory doesn't fit inline in the vector,
+        // and half of them still waiting for the first half to finish.
+        let alloc: Box<dyn DynMemAllocator> = Box::new(BestFit::new(64));
+        let (_, _, alloc) = (0..32)
+            .fold(alloc, |alloc, pid| {
+                alloc.request(MemoryRequest {
+                    process: Pid(pid),
+                    size: 4,
+                    lifetime: 5,
+                    align: 1,
+                })
+            })
+            .tick();
+        let before = alloc.snapshot();
+        let (_, _, alloc) = alloc.tick();
+        let after = alloc.snapshot();
+        // nothing was allocated or freed, so it's the same memory...
+        assert!(before.mem.ptr_eq(&after.mem));
+        // ...and the same queue.
+        assert_eq!(after.requests().len(), 16);
+        assert!(before.reqs.ptr_eq(&after.reqs));
+        assert_eq!(
+            before.layout()[0],
+            MemoryRegion(Owner::Process(Pid(0), 5), 0)
+        );
+        assert_eq!(
+            after.layout()[0],
+            MemoryRegion(Owner::Process(Pid(0), 4), 0)
+        );
+    }
 }
@@ -0,0 +1,102 @@
+use super::{
+    fit::{Fit, FitPolicy, Hole, Holes},
+    MemoryRequest,
+};
+
+/// Picks the first hole on the free list the request fits in, which is
+/// the one closest to the start of memory unless the list is in another
+/// order (see [`ListOrder`](super::ListOrder)).
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub struct First;
+
+impl FitPolicy for First {
+    fn name(&self) -> &'static str {
+        "First Fit"
+    }
+
+    fn select(&mut self, _req: &MemoryRequest, holes: &Holes) -> Option<Hole> {
+        holes.in_list_order().next()
+    }
+}
+
+pub type FirstFit = Fit<First>;
+
+#[cfg(test)]
+mod tests {
+    use crate::strategies::{
+        AllocOptions, ListOrder, MemAllocator, MemoryRegion, MemoryRequest, Owner, Pid,
+    };
+
+    use super::FirstFit;
+
+    #[test]
+    fn basic_first_fit_test() {
+        let mut allocator = FirstFit::new(128);
+        allocator.set_layout(vec![
+            MemoryRegion(Owner::Process(Pid(0), 3), 0),
+            MemoryRegion(Owner::Free, 15), // gap of 6
+            MemoryRegion(Owner::Process(Pid(2), 3), 21),
+            MemoryRegion(Owner::Free, 22), // gap of 3
+            MemoryRegion(Owner::Process(Pid(3), 3), 25),
+            MemoryRegion(Owner::Free, 128),
+        ]);
+        assert_eq!(
+            allocator
+                .request(MemoryRequest {
+                    process: Pid(1),
+                    size: 3,
+                    lifetime: 3,
+                    align: 1,
+                })
+                .tick()
+                .0,
+            vec![
+                MemoryRegion(Owner::Process(Pid(0), 2), 0),
+                MemoryRegion(Owner::Process(Pid(1), 3), 15), // first gap that fits, now a gap of 3.
+                MemoryRegion(Owner::Free, 18),
+                MemoryRegion(Owner::Process(Pid(2), 2), 21),
+                MemoryRegion(Owner::Free, 22),
+                MemoryRegion(Owner::Process(Pid(3), 2), 25),
+                MemoryRegion(Owner::Free, 128),
+            ]
+        );
+    }
+
+    #[test]
+    fn free_list_orders() {
+        let req = |pid, size, lifetime| MemoryRequest {
+            process: Pid(pid),
+            size,
+            lifetime,
+            align: 1,
+        };
+        // where the last two processes go.
+        for (order, expected) in [
+            (ListOrder::Address, (0, 5)),
+            // the hole the first process leaves, then the third's.
+            (ListOrder::Lifo, (0, 20)),
+            // the hole that's been there from the start, twice.
+            (ListOrder::Fifo, (40, 45)),
+        ] {
+            let mut alloc = FirstFit::new(128).with_options(AllocOptions {
+                free_list: order,
+                ..AllocOptions::default()
+            });
+            for (pid, lifetime) in [(1, 1), (2, 5), (3, 2), (4, 5)] {
+                alloc = alloc.request(req(pid, 10, lifetime));
+            }
+            let (_, _, alloc) = alloc.tick();
+            let (_, _, alloc) = alloc.request(req(5, 5, 5)).tick();
+            let (mem, _, _) = alloc.request(req(6, 5, 5)).tick();
+            let placed = |pid| {
+                mem.iter()
+                    .find(
+                        |region| matches!(region.0, Owner::Process(owner, _) if owner == Pid(pid)),
+                    )
+                    .unwrap()
+                    .1
+            };
+            assert_eq!((placed(5), placed(6)), expected, "{order:?}");
+        }
+    }
+}
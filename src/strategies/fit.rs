@@ -0,0 +1,602 @@
+use std::{cell::Cell, ops::Range};
+
+use im::{OrdMap, OrdSet, Vector};
+
+use super::{
+    allocate, allocate_top, compacted, compacted_within, expires_at, free_list::FreeList,
+    moved_bytes, next_to_move, padding_in, partially_compacted, serve_queue, time_left, top_start,
+    Addr, AllocOptions, ClassStats, EndStats, Lifetime, ListOrder, MemAllocatorMut, MemoryRegion,
+    MemoryRequest, Owner, Pid, Search, Size, Snapshot,
+};
+
+/// A hole in memory that a request could be placed into.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Hole {
+    /// index of the hole's region in memory.
+    pub index: usize,
+    pub addr: Addr,
+    pub size: Size,
+    /// how much of the start of the hole the request would
+    /// have to skip over to be aligned.
+    pub pad: Size,
+    /// the region right before the hole, if it's not at the start of
+    /// memory, with how long it has left like [`Snapshot::layout`].
+    pub prev: Option<MemoryRegion>,
+    /// the region right after the hole, with how long it has left.
+    pub next: MemoryRegion,
+}
+
+/// Every hole in memory ordered by size, then by address. This is kept
+/// next to the address ordered memory so best and worst fit can find
+/// their hole without looking at all of them.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct HoleIndex(OrdMap<Size, OrdSet<Addr>>);
+
+impl HoleIndex {
+    fn from_layout(mem: &Vector<MemoryRegion>) -> Self {
+        let mut out = Self::default();
+        for (region, next) in mem.iter().zip(mem.iter().skip(1)) {
+            if let MemoryRegion(Owner::Free, start) = *region {
+                out.insert(start, next.1 - start);
+            }
+        }
+        out
+    }
+
+    fn insert(&mut self, addr: Addr, size: Size) {
+        self.0.entry(size).or_default().insert(addr);
+    }
+
+    fn remove(&mut self, addr: Addr, size: Size) {
+        if let Some(addrs) = self.0.get_mut(&size) {
+            addrs.remove(&addr);
+            if addrs.is_empty() {
+                self.0.remove(&size);
+            }
+        }
+    }
+}
+
+/// The holes a request could go into, handed to a [`FitPolicy`].
+pub struct Holes<'a> {
+    req: &'a MemoryRequest,
+    mem: &'a Vector<MemoryRegion>,
+    index: &'a HoleIndex,
+    list: &'a FreeList,
+    /// the tick it is, to work out how long the regions around a hole have left.
+    time: u32,
+    /// how many holes have been looked at so far.
+    examined: Cell<u64>,
+}
+
+impl Holes<'_> {
+    /// the region at `index`, with how long it has left.
+    fn region(&self, index: usize) -> MemoryRegion {
+        time_left(self.mem[index], self.time)
+    }
+
+    /// the hole at the address, if the request fits in it.
+    fn hole_at(&self, addr: Addr, size: Size) -> Option<Hole> {
+        self.examined.set(self.examined.get() + 1);
+        let pad = padding_in(self.req, addr, size)?;
+        let index = self
+            .mem
+            .binary_search_by_key(&addr, |region| region.1)
+            .expect("the hole index is out of sync with memory");
+        Some(Hole {
+            index,
+            addr,
+            size,
+            pad,
+            prev: index.checked_sub(1).map(|i| self.region(i)),
+            next: self.region(index + 1),
+        })
+    }
+
+    /// every hole that's big enough for the request, from the lowest
+    /// address to the highest. This has to look at all of memory.
+    pub fn iter(&self) -> impl Iterator<Item = Hole> + '_ {
+        self.mem
+            .iter()
+            .zip(self.mem.iter().skip(1))
+            .enumerate()
+            .filter(|(_, (a, _))| a.0.is_free())
+            .inspect(|_| self.examined.set(self.examined.get() + 1))
+            .filter_map(|(index, (a, b))| {
+                Some(Hole {
+                    index,
+                    addr: a.1,
+                    size: b.1 - a.1,
+                    pad: padding_in(self.req, a.1, b.1 - a.1)?,
+                    prev: index.checked_sub(1).map(|i| self.region(i)),
+                    next: self.region(index + 1),
+                })
+            })
+    }
+
+    /// every hole that's big enough for the request, in whatever order the
+    /// free list is in. That's the same as [`iter`](Self::iter) unless
+    /// [`AllocOptions::free_list`] says otherwise.
+    pub fn in_list_order(&self) -> impl Iterator<Item = Hole> + '_ {
+        self.list
+            .iter()
+            .filter_map(|(addr, size)| self.hole_at(addr, size))
+    }
+
+    /// the smallest hole the request fits in, with the lowest address
+    /// if there's a tie. Uses the index, so it's O(log n) unless the
+    /// request's alignment rules out holes that would otherwise fit.
+    pub fn smallest(&self) -> Option<Hole> {
+        self.index
+            .0
+            .range(self.req.size..)
+            .flat_map(|(size, addrs)| addrs.iter().map(move |addr| (*addr, *size)))
+            .find_map(|(addr, size)| self.hole_at(addr, size))
+    }
+
+    /// the biggest hole, with the highest address if there's a tie,
+    /// as long as the request fits in it. Uses the index, so it's O(log n)
+    /// unless the request's alignment rules out the biggest holes.
+    pub fn largest(&self) -> Option<Hole> {
+        self.index
+            .0
+            .range(self.req.size..)
+            .rev()
+            .flat_map(|(size, addrs)| addrs.iter().rev().map(move |addr| (*addr, *size)))
+            .find_map(|(addr, size)| self.hole_at(addr, size))
+    }
+
+    /// the hole with the lowest address out of the ones with a size
+    /// in the range, as long as the request fits in it. This looks at
+    /// every hole size in the range, but not the rest of memory.
+    pub fn first_in(&self, sizes: Range<Size>) -> Option<Hole> {
+        self.index
+            .0
+            .range(sizes.start.max(self.req.size)..sizes.end)
+            .filter_map(|(size, addrs)| addrs.iter().find_map(|addr| self.hole_at(*addr, *size)))
+            .min_by_key(|hole| hole.addr)
+    }
+}
+
+/// The only thing that makes best fit, worst fit, etc. different is
+/// which hole they pick for a request. A [`FitPolicy`] is that choice,
+/// and [`Fit`] does everything else.
+pub trait FitPolicy: Clone {
+    /// the name of the strategy to show people, e.g. `Best Fit`.
+    fn name(&self) -> &'static str;
+    /// picks which hole the request goes into. Returning `None`
+    /// leaves the request waiting. It's `&mut` so a policy can
+    /// keep track of how it's been doing.
+    fn select(&mut self, req: &MemoryRequest, holes: &Holes) -> Option<Hole>;
+    /// called once the request's been put in the hole [`select`](Self::select)
+    /// picked, which only happens once per request. Slabs and the like
+    /// that [`Fit`] puts in memory don't count.
+    fn placed(&mut self, _req: &MemoryRequest, _hole: &Hole) {}
+    /// how often each size class had a hole, for policies that have them.
+    fn classes(&self) -> Vec<ClassStats> {
+        Vec::new()
+    }
+    /// whether the request goes at the top of its hole instead of the bottom.
+    fn at_top(&self, _req: &MemoryRequest) -> bool {
+        false
+    }
+    /// the holes at the bottom and top of memory, for
+    /// policies that keep processes apart by size.
+    fn ends(&self, _mem: &Vector<MemoryRegion>) -> Option<(EndStats, EndStats)> {
+        None
+    }
+}
+
+/// Which [`FitPolicy`] a strategy that's built on top of one uses. Only
+/// strategies that pick from the holes [`Fit`] hands them can be built on.
+/// Next fit and TLSF keep their own track of where to look, so they can't.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum Backing {
+    #[default]
+    Best,
+    First,
+    Worst,
+    Segregated,
+}
+
+/// A memory allocator that places each request in whichever hole
+/// its [`FitPolicy`] picks.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Fit<P> {
+    pub(super) reqs: Vector<MemoryRequest>,
+    pub(super) mem: Vector<MemoryRegion>,
+    holes: HoleIndex,
+    free_list: FreeList,
+    time: u32,
+    /// the biggest alignment anything's asked for, see [`compacted`].
+    align: Size,
+    /// how much looking for holes placing things on this tick took.
+    searched: Search,
+    options: AllocOptions,
+    policy: P,
+}
+
+impl<P: FitPolicy + Default> Fit<P> {
+    pub fn new(mem_size: Size) -> Self {
+        Self::with_policy(mem_size, P::default())
+    }
+}
+
+impl<P: FitPolicy> Fit<P> {
+    pub fn with_policy(mem_size: Size, policy: P) -> Self {
+        let mem = Vector::from(vec![
+            MemoryRegion(Owner::Free, 0),
+            MemoryRegion(Owner::End, mem_size),
+        ]);
+        Self {
+            reqs: Vector::new(),
+            holes: HoleIndex::from_layout(&mem),
+            free_list: FreeList::new(ListOrder::default(), &mem),
+            mem,
+            time: 0,
+            align: 1,
+            searched: Search::default(),
+            options: AllocOptions::default(),
+            policy,
+        }
+    }
+
+    pub fn with_options(mut self, options: AllocOptions) -> Self {
+        self.free_list = FreeList::new(options.free_list, &self.mem);
+        self.options = options;
+        self
+    }
+
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Starts over from `mem`, e.g. so a test can start from a given layout.
+    #[cfg(test)]
+    pub(super) fn set_layout(&mut self, mem: Vec<MemoryRegion>) {
+        self.mem = mem.into();
+        self.holes = HoleIndex::from_layout(&self.mem);
+        self.free_list.sync(&self.mem);
+    }
+
+    /// Fulfills pending memory allocation requests by placing them in the slot
+    /// the policy picks. Requests that don't fit anywhere stay queued, in the
+    /// same order.
+    /// Modifies internal state.
+    fn fullfill_reqs(&mut self) {
+        self.reqs = serve_queue(self.reqs.clone(), |req| self.place(*req));
+    }
+
+    /// Places a single request, returning false if the policy
+    /// couldn't find anywhere to put it.
+    fn place(&mut self, req: MemoryRequest) -> bool {
+        let rounded = self.options.rounded(&req);
+        let holes = Holes {
+            req: &rounded,
+            mem: &self.mem,
+            index: &self.holes,
+            list: &self.free_list,
+            time: self.time,
+            examined: Cell::new(0),
+        };
+        let selected = self.policy.select(&rounded, &holes);
+        self.searched.record(holes.examined.get());
+        let Some(hole) = selected else {
+            return false;
+        };
+        self.policy.placed(&req, &hole);
+        let Hole {
+            index,
+            addr,
+            size,
+            pad,
+            ..
+        } = hole;
+        self.holes.remove(addr, size);
+        let expiry = expires_at(self.time, req.lifetime);
+        if self.policy.at_top(&rounded) {
+            let leftover = top_start(&req, addr + size, &self.options) - addr;
+            let leftover = self.options.keeps(leftover).then_some((addr, leftover));
+            if let Some((addr, leftover)) = leftover {
+                self.holes.insert(addr, leftover);
+            }
+            self.free_list.shrink(addr, leftover);
+            allocate_top(&mut self.mem, index, &req, expiry, &self.options);
+            return true;
+        }
+        let leftover = size - pad - rounded.size;
+        let leftover = self
+            .options
+            .keeps(leftover)
+            .then_some((addr + size - leftover, leftover));
+        if let Some((addr, leftover)) = leftover {
+            self.holes.insert(addr, leftover);
+        }
+        self.free_list.shrink(addr, leftover);
+        allocate(&mut self.mem, index, pad, &req, expiry, &self.options);
+        true
+    }
+
+    /// Puts something that isn't a process (e.g. a slab) in the hole the
+    /// policy picks, at a multiple of `align`. Returns where it went.
+    pub(super) fn reserve(&mut self, size: Size, align: Size, owner: Owner) -> Option<Addr> {
+        let req = MemoryRequest {
+            process: Pid(0),
+            size,
+            lifetime: 0,
+            align,
+        };
+        let holes = Holes {
+            req: &req,
+            mem: &self.mem,
+            index: &self.holes,
+            list: &self.free_list,
+            time: self.time,
+            examined: Cell::new(0),
+        };
+        let selected = self.policy.select(&req, &holes);
+        self.searched.record(holes.examined.get());
+        let Hole {
+            index,
+            addr,
+            size: hole_size,
+            pad,
+            ..
+        } = selected?;
+        self.align = self.align.max(align);
+        self.holes.remove(addr, hole_size);
+        let start = addr + pad;
+        let leftover = hole_size - pad - size;
+        // whatever's either side of it is still a hole.
+        let regions = [
+            (pad > 0).then_some(MemoryRegion(Owner::Free, addr)),
+            Some(MemoryRegion(owner, start)),
+            (leftover > 0).then_some(MemoryRegion(Owner::Free, start + size)),
+        ];
+        self.mem.remove(index);
+        for region in regions.into_iter().flatten().rev() {
+            self.mem.insert(index, region);
+        }
+        self.free_list.remove(addr);
+        if pad > 0 {
+            self.holes.insert(addr, pad);
+            self.free_list.add(addr, pad);
+        }
+        if leftover > 0 {
+            self.holes.insert(start + size, leftover);
+            self.free_list.add(start + size, leftover);
+        }
+        Some(start)
+    }
+
+    /// Frees something [`reserve`](Self::reserve) put in memory.
+    pub(super) fn release(&mut self, addr: Addr) {
+        let index = self
+            .mem
+            .binary_search_by_key(&addr, |region| region.1)
+            .expect("only reserved regions are released");
+        self.free_regions(vec![index]);
+    }
+
+    /// Deallocates memory regions that have expired and merges neighboring regions.
+    fn dealloc(&mut self) {
+        let time = Lifetime::from(self.time);
+        self.free_where(|_, expiry| expiry <= time);
+    }
+
+    /// Frees every region held by a process `should_free` picks, given the
+    /// process and the tick it expires at.
+    fn free_where(&mut self, should_free: impl Fn(Pid, Lifetime) -> bool) {
+        let freed = self
+            .mem
+            .iter()
+            .enumerate()
+            .filter(|(_, region)| {
+                matches!(region.0.held_by(), Some((pid, expiry)) if should_free(pid, expiry))
+            })
+            .map(|(i, _)| i)
+            .collect();
+        self.free_regions(freed);
+    }
+
+    /// Frees the regions at the indexes, which go from lowest to highest,
+    /// and merges each with whatever holes are either side of it. Only the
+    /// holes that were merged and the ones they were merged into change in
+    /// the hole index and the free list, rather than building them again.
+    fn free_regions(&mut self, freed: Vec<usize>) {
+        // every run of freed regions and the holes around them becomes one hole.
+        let mut runs: Vec<Range<usize>> = vec![];
+        let mut freed = freed.into_iter().peekable();
+        while let Some(index) = freed.next() {
+            let mut start = index;
+            while start > 0 && self.mem[start - 1].0.is_free() {
+                start -= 1;
+            }
+            let mut end = index + 1;
+            while self.mem[end].0.is_free() || freed.next_if_eq(&end).is_some() {
+                end += 1;
+            }
+            runs.push(start..end);
+        }
+        for run in &runs {
+            for i in run.clone().filter(|&i| self.mem[i].0.is_free()) {
+                let addr = self.mem[i].1;
+                self.holes.remove(addr, self.mem[i + 1].1 - addr);
+                self.free_list.remove(addr);
+            }
+            let addr = self.mem[run.start].1;
+            let size = self.mem[run.end].1 - addr;
+            self.holes.insert(addr, size);
+            self.free_list.add(addr, size);
+        }
+        // from the top down, so the runs below are still where they were.
+        for run in runs.into_iter().rev() {
+            self.mem[run.start].0 = Owner::Free;
+            for _ in run.start + 1..run.end {
+                self.mem.remove(run.start + 1);
+            }
+        }
+    }
+}
+
+impl<P: FitPolicy> MemAllocatorMut for Fit<P> {
+    /// Adds the request to the queue, it's served on the next tick.
+    fn request(&mut self, req: MemoryRequest) {
+        self.align = self.align.max(req.align);
+        self.reqs.push_back(req.at_least_a_byte());
+    }
+
+    /// Advances the simulation by one time unit, deallocating the regions
+    /// that expired and fulfilling pending requests.
+    fn tick(&mut self) {
+        self.time += 1;
+        self.searched = Search::default();
+        self.dealloc();
+        self.fullfill_reqs();
+    }
+
+    fn free(&mut self, pid: Pid) {
+        self.free_where(|owner, _| owner == pid);
+    }
+
+    fn compact(&mut self) -> Size {
+        let mem = compacted(&self.mem, self.align);
+        let before = std::mem::replace(&mut self.mem, mem);
+        self.holes = HoleIndex::from_layout(&self.mem);
+        self.free_list.sync(&self.mem);
+        moved_bytes(&before, &self.mem)
+    }
+
+    fn compact_for(&mut self, req: &MemoryRequest) -> Option<Size> {
+        let mem = partially_compacted(&self.mem, self.align, &self.options.rounded(req))?;
+        let before = std::mem::replace(&mut self.mem, mem);
+        self.holes = HoleIndex::from_layout(&self.mem);
+        self.free_list.sync(&self.mem);
+        Some(moved_bytes(&before, &self.mem))
+    }
+
+    fn compact_step(&mut self, budget: Size) -> (Size, Option<Addr>) {
+        let (mem, moved, next) = compacted_within(&self.mem, self.align, budget);
+        self.mem = mem;
+        self.holes = HoleIndex::from_layout(&self.mem);
+        self.free_list.sync(&self.mem);
+        (moved, next)
+    }
+
+    fn next_to_move(&self) -> Option<MemoryRegion> {
+        next_to_move(&self.mem, self.align)
+    }
+
+    fn name(&self) -> &'static str {
+        self.policy.name()
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            mem: self.mem.clone(),
+            reqs: self.reqs.clone(),
+            time: self.time,
+            slabs: Vector::new(),
+            rover: None,
+            moved: 0,
+            moving: None,
+            searched: self.searched,
+            classes: self.policy.classes().into(),
+            ends: self.policy.ends(&self.mem),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::strategies::{
+        AllocOptions, BestFit, FirstFit, ListOrder, MemAllocator, MemAllocatorMut, MemoryRequest,
+        Pid, ScoreFit, Scored, WorstFit,
+    };
+
+    use super::HoleIndex;
+
+    /// runs the same requests through both allocators, checking
+    /// memory matches after every tick.
+    fn assert_same_layouts(mut a: impl MemAllocator, mut b: impl MemAllocator) {
+        let mut rng = StdRng::seed_from_u64(405);
+        for i in 0..200 {
+            // sizes are multiples of 8 so there's plenty of
+            // holes with the same size to break ties between.
+            for pid in i * 3..i * 3 + 3 {
+                let req = MemoryRequest {
+                    process: Pid(pid),
+                    size: rng.gen_range(1..8) * 8,
+                    lifetime: rng.gen_range(0..8),
+                    align: 1,
+                };
+                a = a.request(req);
+                b = b.request(req);
+            }
+            let (mem_a, _, next_a) = a.tick();
+            let (mem_b, _, next_b) = b.tick();
+            assert_eq!(mem_a, mem_b, "tick {i}");
+            (a, b) = (next_a, next_b);
+        }
+    }
+
+    /// the index should pick the exact same holes as looking through
+    /// every hole did.
+    #[test]
+    fn index_matches_scanning() {
+        assert_same_layouts(
+            BestFit::new(512),
+            ScoreFit::with_policy(
+                512,
+                Scored::new("Scanning Best Fit", |_, hole| Some(-(hole.size as i64))),
+            ),
+        );
+        assert_same_layouts(
+            WorstFit::new(512),
+            ScoreFit::with_policy(
+                512,
+                Scored::new("Scanning Worst Fit", |_, hole| {
+                    Some(((hole.size as i64) << 32) + hole.addr as i64)
+                }),
+            ),
+        );
+    }
+
+    /// the index and the free list are only told about the holes that
+    /// change, so they should still end up the same as going through all
+    /// of memory again.
+    #[test]
+    fn index_stays_in_sync() {
+        for order in [ListOrder::Address, ListOrder::Lifo, ListOrder::Fifo] {
+            let mut rng = StdRng::seed_from_u64(33);
+            let mut alloc = FirstFit::new(4096).with_options(AllocOptions {
+                free_list: order,
+                ..AllocOptions::default()
+            });
+            for i in 0..300 {
+                // few enough that there's usually holes left over to merge with.
+                for pid in i * 2..i * 2 + 2 {
+                    MemAllocatorMut::request(
+                        &mut alloc,
+                        MemoryRequest {
+                            process: Pid(pid),
+                            size: rng.gen_range(1..256),
+                            lifetime: rng.gen_range(0..8),
+                            align: 1 << rng.gen_range(0..4),
+                        },
+                    );
+                }
+                if i % 10 == 9 {
+                    MemAllocatorMut::free(&mut alloc, Pid(i * 2 - 10));
+                }
+                MemAllocatorMut::tick(&mut alloc);
+                assert_eq!(alloc.holes, HoleIndex::from_layout(&alloc.mem), "tick {i}");
+                let mut rescanned = alloc.free_list.clone();
+                rescanned.sync(&alloc.mem);
+                assert_eq!(alloc.free_list, rescanned, "tick {i}");
+            }
+        }
+    }
+}
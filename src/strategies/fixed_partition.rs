@@ -0,0 +1,327 @@
+use im::Vector;
+
+use super::{
+    expires_at, padding_in, serve_queue, Addr, AllocOptions, Lifetime, MemAllocatorMut,
+    MemoryRegion, MemoryRequest, Owner, Pid, Search, Size, Snapshot,
+};
+
+/// How memory is cut up into partitions.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum Partitions {
+    /// this many partitions, all the same size.
+    Equal(u32),
+    /// partitions of these sizes, in order from the start of memory.
+    /// Whatever memory is left over after them is one more partition.
+    Sizes(Vec<Size>),
+}
+
+impl Default for Partitions {
+    fn default() -> Self {
+        Partitions::Equal(4)
+    }
+}
+
+impl Partitions {
+    /// where each partition starts and how big it is.
+    fn table(&self, mem_size: Size) -> Vec<(Addr, Size)> {
+        let sizes = match self {
+            Partitions::Equal(count) => {
+                let count = Size::from(*count).max(1);
+                let mut sizes = vec![mem_size / count; count as usize];
+                // the last one picks up whatever doesn't divide evenly.
+                *sizes.last_mut().unwrap() += mem_size % count;
+                sizes
+            }
+            Partitions::Sizes(sizes) => sizes.clone(),
+        };
+        let mut out = vec![];
+        let mut addr = 0;
+        for size in sizes {
+            let size = size.min(mem_size - addr);
+            if size == 0 {
+                break;
+            }
+            out.push((addr, size));
+            addr += size;
+        }
+        if addr < mem_size {
+            out.push((addr, mem_size - addr));
+        }
+        out
+    }
+}
+
+/// How processes wait for a partition.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum Queueing {
+    /// everyone waits in one line, and takes the smallest
+    /// free partition they fit in when it's their turn.
+    #[default]
+    Single,
+    /// every partition has its own line. Processes join the line of the
+    /// smallest partition they fit in (the shortest line if there's a tie),
+    /// even if a bigger partition is sitting free.
+    PerPartition,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+struct Partition {
+    start: Addr,
+    size: Size,
+    /// the process in the partition, and the tick it expires at.
+    holder: Option<(MemoryRequest, Lifetime)>,
+}
+
+/// A request waiting for a partition.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+struct Waiting {
+    req: MemoryRequest,
+    /// the partition whose line it's in, with [`Queueing::PerPartition`].
+    partition: Option<usize>,
+}
+
+/// Fixed partitioning (MFT). Memory is cut up into partitions ahead of
+/// time, and each process gets a whole partition to itself. What it
+/// doesn't use of the partition is internal fragmentation, but there's
+/// never any external fragmentation to speak of.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct FixedPartition {
+    partitions: Vector<Partition>,
+    reqs: Vector<Waiting>,
+    mem_size: Size,
+    time: u32,
+    options: AllocOptions,
+}
+
+impl FixedPartition {
+    pub fn new(mem_size: Size) -> Self {
+        Self::with_options(mem_size, AllocOptions::default())
+    }
+
+    pub fn with_options(mem_size: Size, options: AllocOptions) -> Self {
+        Self {
+            partitions: options
+                .partitions
+                .table(mem_size)
+                .into_iter()
+                .map(|(start, size)| Partition {
+                    start,
+                    size,
+                    holder: None,
+                })
+                .collect(),
+            reqs: Vector::new(),
+            mem_size,
+            time: 0,
+            options,
+        }
+    }
+
+    fn fits(&self, req: &MemoryRequest, partition: &Partition) -> bool {
+        padding_in(&self.options.rounded(req), partition.start, partition.size).is_some()
+    }
+
+    /// the smallest partition the request fits in, with the lowest address if
+    /// there's a tie. With `free_only` it has to be free right now as well.
+    fn smallest_fit(&self, req: &MemoryRequest, free_only: bool) -> Option<usize> {
+        self.partitions
+            .iter()
+            .enumerate()
+            .filter(|(_, partition)| !free_only || partition.holder.is_none())
+            .filter(|(_, partition)| self.fits(req, partition))
+            .min_by_key(|(i, partition)| (partition.size, *i))
+            .map(|(i, _)| i)
+    }
+
+    /// the line a new request should join, with [`Queueing::PerPartition`].
+    fn line_for(&self, req: &MemoryRequest) -> Option<usize> {
+        let size = self.partitions[self.smallest_fit(req, false)?].size;
+        let line_len = |i: usize| self.reqs.iter().filter(|w| w.partition == Some(i)).count();
+        (0..self.partitions.len())
+            .filter(|i| self.partitions[*i].size == size && self.fits(req, &self.partitions[*i]))
+            .min_by_key(|i| (line_len(*i), *i))
+    }
+
+    fn fullfill_reqs(&mut self) {
+        self.reqs = serve_queue(self.reqs.clone(), |w| {
+            let partition = match self.options.queueing {
+                Queueing::Single => self.smallest_fit(&w.req, true),
+                // only the front of each line gets a look in, since
+                // everyone behind it sees the partition's been taken.
+                Queueing::PerPartition => {
+                    w.partition.filter(|i| self.partitions[*i].holder.is_none())
+                }
+            };
+            let Some(i) = partition else {
+                return false;
+            };
+            self.partitions[i].holder = Some((w.req, expires_at(self.time, w.req.lifetime)));
+            true
+        });
+    }
+
+    fn free_where(&mut self, should_free: impl Fn(Pid, Lifetime) -> bool) {
+        for partition in self.partitions.iter_mut() {
+            if matches!(partition.holder, Some((req, expiry)) if should_free(req.process, expiry)) {
+                partition.holder = None;
+            }
+        }
+    }
+}
+
+impl MemAllocatorMut for FixedPartition {
+    fn request(&mut self, req: MemoryRequest) {
+        let req = req.at_least_a_byte();
+        let partition = match self.options.queueing {
+            Queueing::Single => None,
+            Queueing::PerPartition => self.line_for(&req),
+        };
+        self.reqs.push_back(Waiting { req, partition });
+    }
+
+    fn tick(&mut self) {
+        self.time += 1;
+        let time = self.time;
+        self.free_where(|_, expiry| expiry <= Lifetime::from(time));
+        self.fullfill_reqs();
+    }
+
+    fn free(&mut self, pid: Pid) {
+        self.free_where(|owner, _| owner == pid);
+    }
+
+    /// Partitions can't move, so there's nothing to compact.
+    fn compact(&mut self) -> Size {
+        0
+    }
+
+    fn compact_for(&mut self, _req: &MemoryRequest) -> Option<Size> {
+        None
+    }
+
+    fn compact_step(&mut self, _budget: Size) -> (Size, Option<Addr>) {
+        (0, None)
+    }
+
+    fn next_to_move(&self) -> Option<MemoryRegion> {
+        None
+    }
+
+    fn name(&self) -> &'static str {
+        "Fixed Partitions"
+    }
+
+    /// Lays the partitions out like every other allocator does, so they can be
+    /// shown side by side. Free partitions are holes, and whatever a process
+    /// doesn't use of its partition is rounding, up to the next allocation
+    /// unit, then padding.
+    fn snapshot(&self) -> Snapshot {
+        let mut mem = Vector::new();
+        for partition in &self.partitions {
+            let Some((req, expiry)) = partition.holder else {
+                mem.push_back(MemoryRegion(Owner::Free, partition.start));
+                continue;
+            };
+            let pad = padding_in(&req, partition.start, partition.size)
+                .expect("processes always fit in their partition");
+            if pad > 0 {
+                mem.push_back(MemoryRegion(
+                    Owner::Padding(req.process, expiry),
+                    partition.start,
+                ));
+            }
+            let start = partition.start + pad;
+            mem.push_back(MemoryRegion(Owner::Process(req.process, expiry), start));
+            let granted = start + self.options.granted(req.size);
+            if granted > start + req.size {
+                mem.push_back(MemoryRegion(
+                    Owner::Rounding(req.process, expiry),
+                    start + req.size,
+                ));
+            }
+            if granted < partition.start + partition.size {
+                mem.push_back(MemoryRegion(Owner::Padding(req.process, expiry), granted));
+            }
+        }
+        mem.push_back(MemoryRegion(Owner::End, self.mem_size));
+        Snapshot {
+            mem,
+            reqs: self.reqs.iter().map(|w| w.req).collect(),
+            time: self.time,
+            slabs: Vector::new(),
+            rover: None,
+            moved: 0,
+            moving: None,
+            searched: Search::default(),
+            classes: Vector::new(),
+            ends: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::strategies::{AllocOptions, MemAllocator, MemoryRegion, MemoryRequest, Owner, Pid};
+
+    use super::{FixedPartition, Partitions, Queueing};
+
+    fn run(queueing: Queueing) -> (Vec<MemoryRegion>, Vec<MemoryRequest>) {
+        let options = AllocOptions {
+            partitions: Partitions::Sizes(vec![16, 32]),
+            queueing,
+            ..AllocOptions::default()
+        };
+        // partitions of 16, 32 and whatever's left (80).
+        let (mem, reqs, _) = [(1, 10), (2, 12), (3, 100)]
+            .into_iter()
+            .fold(
+                FixedPartition::with_options(128, options),
+                |alloc, (pid, size)| {
+                    alloc.request(MemoryRequest {
+                        process: Pid(pid),
+                        size,
+                        lifetime: 2,
+                        align: 1,
+                    })
+                },
+            )
+            .tick();
+        (mem, reqs)
+    }
+
+    #[test]
+    fn single_queue() {
+        let (mem, reqs) = run(Queueing::Single);
+        assert_eq!(
+            mem,
+            vec![
+                MemoryRegion(Owner::Process(Pid(1), 2), 0),
+                MemoryRegion(Owner::Padding(Pid(1), 2), 10),
+                // the 16 is taken, so the next smallest it is.
+                MemoryRegion(Owner::Process(Pid(2), 2), 16),
+                MemoryRegion(Owner::Padding(Pid(2), 2), 28),
+                MemoryRegion(Owner::Free, 48),
+                MemoryRegion(Owner::End, 128),
+            ]
+        );
+        // too big for any partition.
+        assert_eq!(reqs.len(), 1);
+    }
+
+    #[test]
+    fn per_partition_queues() {
+        let (mem, reqs) = run(Queueing::PerPartition);
+        assert_eq!(
+            mem,
+            vec![
+                MemoryRegion(Owner::Process(Pid(1), 2), 0),
+                MemoryRegion(Owner::Padding(Pid(1), 2), 10),
+                // waiting on the 16, even though the 32 is free.
+                MemoryRegion(Owner::Free, 16),
+                MemoryRegion(Owner::Free, 48),
+                MemoryRegion(Owner::End, 128),
+            ]
+        );
+        assert_eq!(reqs.len(), 2);
+    }
+}
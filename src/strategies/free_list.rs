@@ -0,0 +1,126 @@
+use im::{OrdMap, Vector};
+
+use super::{Addr, MemoryRegion, Owner, Size};
+
+/// The order a free list keeps its holes in, which decides which hole
+/// first fit (and next fit) comes across first.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum ListOrder {
+    /// from the start of memory to the end, the same as looking
+    /// through memory itself.
+    #[default]
+    Address,
+    /// the most recently freed hole first.
+    Lifo,
+    /// the least recently freed hole first.
+    Fifo,
+}
+
+/// Every hole in memory, in whichever order the free list is meant to be in.
+/// Memory that's freed goes on the list as a new hole, along with anything it
+/// got merged with, but what's left of a hole after a process is put in it
+/// keeps the hole's place.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub(super) struct FreeList {
+    order: ListOrder,
+    /// how many holes have gone on the list, so they can be told apart by age.
+    added: u64,
+    /// every hole's size, and where it is in `list`.
+    holes: OrdMap<Addr, (Size, u64)>,
+    list: OrdMap<u64, Addr>,
+}
+
+impl FreeList {
+    pub(super) fn new(order: ListOrder, mem: &Vector<MemoryRegion>) -> Self {
+        let mut out = Self {
+            order,
+            ..Self::default()
+        };
+        out.sync(mem);
+        out
+    }
+
+    /// where a hole that's just gone on the list goes in it.
+    fn new_key(&mut self, addr: Addr) -> u64 {
+        self.added += 1;
+        match self.order {
+            ListOrder::Address => addr,
+            ListOrder::Lifo => u64::MAX - self.added,
+            ListOrder::Fifo => self.added,
+        }
+    }
+
+    fn insert(&mut self, addr: Addr, size: Size, key: u64) {
+        self.holes.insert(addr, (size, key));
+        self.list.insert(key, addr);
+    }
+
+    /// Catches up with memory after processes have been freed or moved.
+    /// Holes that are exactly where they were keep their place, and
+    /// anything else is new.
+    pub(super) fn sync(&mut self, mem: &Vector<MemoryRegion>) {
+        let old = std::mem::take(&mut self.holes);
+        self.list = OrdMap::new();
+        for (region, next) in mem.iter().zip(mem.iter().skip(1)) {
+            if let MemoryRegion(Owner::Free, addr) = *region {
+                let size = next.1 - addr;
+                let key = match old.get(&addr) {
+                    Some((old_size, key)) if *old_size == size => *key,
+                    _ => self.new_key(addr),
+                };
+                self.insert(addr, size, key);
+            }
+        }
+    }
+
+    /// Takes the hole at `addr` off the list, e.g. once it's been merged
+    /// into a bigger one.
+    pub(super) fn remove(&mut self, addr: Addr) {
+        if let Some((_, key)) = self.holes.remove(&addr) {
+            self.list.remove(&key);
+        }
+    }
+
+    /// Puts a hole that's just been freed (or split off of one) on the list.
+    pub(super) fn add(&mut self, addr: Addr, size: Size) {
+        let key = self.new_key(addr);
+        self.insert(addr, size, key);
+    }
+
+    /// A process has gone in the hole at `addr`, and `leftover` is
+    /// whatever's still free of it, which keeps the hole's place.
+    pub(super) fn shrink(&mut self, addr: Addr, leftover: Option<(Addr, Size)>) {
+        let Some((_, key)) = self.holes.remove(&addr) else {
+            return;
+        };
+        self.list.remove(&key);
+        if let Some((addr, size)) = leftover {
+            let key = match self.order {
+                // the key is the address, which might've moved.
+                ListOrder::Address => addr,
+                ListOrder::Lifo | ListOrder::Fifo => key,
+            };
+            self.insert(addr, size, key);
+        }
+    }
+
+    /// every hole's address and size, in list order.
+    pub(super) fn iter(&self) -> impl Iterator<Item = (Addr, Size)> + '_ {
+        self.list.values().map(|addr| (*addr, self.holes[addr].0))
+    }
+
+    /// every hole in list order, but starting from the first hole at or
+    /// after `addr` and wrapping back around to the front of the list.
+    pub(super) fn iter_from(&self, addr: Addr) -> impl Iterator<Item = (Addr, Size)> + '_ {
+        let key = self
+            .holes
+            .range(addr..)
+            .next()
+            .or_else(|| self.holes.iter().next())
+            .map_or(0, |(_, (_, key))| *key);
+        self.list
+            .range(key..)
+            .chain(self.list.range(..key))
+            .map(|(_, addr)| (*addr, self.holes[addr].0))
+    }
+}
@@ -1,166 +1,234 @@
-use std::collections::VecDeque;
+use im::Vector;
 
-use super::{MemAllocator, MemoryRegion, MemoryRequest, Pid, FINAL_MEM_REGION_PID};
+use super::{
+    allocate, compacted, compacted_within, expires_at, free_list::FreeList, free_where,
+    moved_bytes, next_to_move, padding_in, partially_compacted, serve_queue, Addr, AllocOptions,
+    Lifetime, ListOrder, MemAllocatorMut, MemoryRegion, MemoryRequest, Owner, Pid, Search, Size,
+    Snapshot,
+};
+
+/// Where [`NextFit`]'s rover goes after it's placed a process.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum RoverPolicy {
+    /// stays at the start of the process, so the next search
+    /// starts with the process (and skips straight past it).
+    #[default]
+    Stay,
+    /// moves to the end of the process.
+    Past,
+    /// moves to the end of the process, but goes back to the start of
+    /// memory whenever something in front of it is freed, so the hole
+    /// that opens up (and whatever it's merged with) gets used first.
+    Reset,
+}
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
 pub struct NextFit {
-    reqs: VecDeque<MemoryRequest>,
-    mem: Vec<MemoryRegion>,
-    // offset into the memory, which is where it's
-    // last gone and searched through.
-    offset: usize,
+    reqs: Vector<MemoryRequest>,
+    mem: Vector<MemoryRegion>,
+    // the address the next search starts from. It's kept as an address
+    // rather than an index so it stays put when regions get merged.
+    rover: Addr,
+    free_list: FreeList,
     time: u32,
+    // the biggest alignment anything's asked for, so
+    // compacting doesn't knock anything out of line.
+    align: Size,
+    // how much looking for holes placing things on this tick took.
+    searched: Search,
+    options: AllocOptions,
 }
 
 impl NextFit {
     #[allow(unused)]
-    pub fn new(mem_size: u32) -> Self {
+    pub fn new(mem_size: Size) -> Self {
+        let mem = Vector::from(vec![
+            MemoryRegion(Owner::Free, 0),
+            MemoryRegion(Owner::End, mem_size),
+        ]);
         Self {
-            reqs: VecDeque::new(),
-            mem: vec![
-                MemoryRegion(None, 0),
-                MemoryRegion(Some((Pid(FINAL_MEM_REGION_PID), -1)), mem_size),
-            ],
+            reqs: Vector::new(),
+            free_list: FreeList::new(ListOrder::default(), &mem),
+            mem,
             time: 0,
-            offset: 0,
+            rover: 0,
+            align: 1,
+            searched: Search::default(),
+            options: AllocOptions::default(),
         }
     }
 
-    fn fullfill_reqs(mut self) -> Self {
+    pub fn with_options(mut self, options: AllocOptions) -> Self {
+        self.free_list = FreeList::new(options.free_list, &self.mem);
+        self.options = options;
+        self
+    }
+
+    /// the address the next search starts from.
+    pub fn rover(&self) -> Addr {
+        self.rover
+    }
 
-         // Attempt to pop the front of the requests queue.
-        let Some(req) = self.reqs.pop_front() else {
-            // if we have no requests nothing to do.
-            return self;
+    /// the index of the region the rover's in.
+    fn offset(&self) -> usize {
+        let index = match self
+            .mem
+            .binary_search_by_key(&self.rover, |region| region.1)
+        {
+            Ok(index) => index,
+            Err(after) => after - 1,
         };
+        // it can't start from the end of memory, so that's the start instead.
+        if index == self.mem.len() - 1 {
+            0
+        } else {
+            index
+        }
+    }
 
-        // Find a fitting memory region for the current request.
-        let fitting_region = self.mem[self.offset..]
-            .windows(2)
-            .chain(self.mem[..self.offset + 1].windows(2))
-            .enumerate()
-            .find(|&(_, item)| {
-                let [a, b]: [MemoryRegion; 2] = item.try_into().unwrap();
-                if a.0.is_some() {
-                    // this memory region belongs to a process, we can't allocate here.
-                    return false;
-                }
-                if b.1 - a.1 < req.size {
-                    // this memory region is too small.
-                    return false;
-                }
-                true
+    fn fullfill_reqs(&mut self) {
+        self.reqs = serve_queue(self.reqs.clone(), |req| self.place(*req));
+    }
+
+    fn place(&mut self, req: MemoryRequest) -> bool {
+        // Find a fitting hole for the current request, starting from the one
+        // the rover's in (or the next one along) and going round the free
+        // list, wrapping back around to the start. With the list in address
+        // order that's just going through memory from the rover.
+        // it has to fit once it's been rounded up.
+        let rounded = self.options.rounded(&req);
+        let mut examined = 0;
+        let fitting_hole = self
+            .free_list
+            .iter_from(self.mem[self.offset()].1)
+            .inspect(|_| examined += 1)
+            .find_map(|(addr, size)| {
+                // this is None if the hole is too small once we've lined the request up.
+                Some((addr, size, padding_in(&rounded, addr, size)?))
             });
-        let Some((index_from_offset, _)) = fitting_region else {
-            // we couldn't find one, so do the other requests,
-            // and then exit out.
-            let mut out = self.fullfill_reqs();
-            out.reqs.push_front(req);
-            return out;
+        self.searched.record(examined);
+        let Some((addr, size, pad)) = fitting_hole else {
+            // we couldn't find one, leave it for later.
+            return false;
         };
+        let index = self
+            .mem
+            .binary_search_by_key(&addr, |region| region.1)
+            .expect("the free list is out of sync with memory");
+        let leftover = size - pad - rounded.size;
+        self.free_list.shrink(
+            addr,
+            self.options
+                .keeps(leftover)
+                .then_some((addr + size - leftover, leftover)),
+        );
 
-        // increment the offset by how much we moved.
-        // this is the current index we want to insert into.
-        self.offset += index_from_offset;
-        self.offset %= self.mem.len() - 1;
-
-        // when inserting into the memory region we have to be careful that the next memory region
-        // does not end up having the same starting point as the one after it (e.g. the next
-        // region should not have a memory size of 0). If we do... prune it out.
-        self.mem.insert(
-            self.offset,
-            MemoryRegion(
-                Some((req.process, req.lifetime as _)),
-                self.mem[self.offset].1,
-            ),
+        let process = allocate(
+            &mut self.mem,
+            index,
+            pad,
+            &req,
+            expires_at(self.time, req.lifetime),
+            &self.options,
         );
-        self.mem[self.offset + 1].1 += req.size;
-        match self.mem.get(self.offset + 2) {
-            Some(MemoryRegion(_, starting)) if *starting == self.mem[self.offset + 1].1 => {
-                self.mem.remove(self.offset + 1);
-            }
-            Some(MemoryRegion(_, starting)) if *starting < self.mem[self.offset + 1].1 => {
-                dbg!((starting, self.mem[self.offset + 1].1));
-                panic!("Overlapping memory regions")
-            }
-            _ => {}
-        }
-        // do the rest of the requests.
-        self.fullfill_reqs()
+        self.rover = match self.options.rover {
+            // the rover ends up on the process, after any padding it needed.
+            RoverPolicy::Stay => self.mem[process].1,
+            RoverPolicy::Past | RoverPolicy::Reset => self.mem[process].1 + rounded.size,
+        };
+        true
     }
 
-    /// Deallocates memory regions with zero size and merges neighboring regions
-    fn dealloc(&self) -> Self {
-        let mut out = self.clone();
-        let offset_mem_addr = out.mem[self.offset].1;
-        out.mem = out
-            .mem
-            .into_iter()
-            .map(|mem| match mem.0 {
-                Some((_, 0)) => MemoryRegion(None, mem.1),
-                _ => mem,
-            })
-            .collect();
-        // merge neighboring regions with the same
-        // owner by removing the second region with the same owner.
-        out.mem = out.mem.windows(2).fold(vec![], |mut acc, regions| {
-            let [prev, next]: [_; 2] = regions.try_into().unwrap();
-            if prev.1 == 0 {
-                acc.push(prev);
-            }
-            if next.0 != prev.0 {
-                acc.push(next);
-            }
-            acc
+    /// Frees whatever `should_free` says to, going back
+    /// to the start if [`RoverPolicy::Reset`] says so.
+    fn free_where(&mut self, should_free: impl Fn(Pid, Lifetime) -> bool) {
+        let freed_in_front = self.mem.iter().any(|region| {
+            region.1 < self.rover
+                && matches!(region.0.held_by(), Some((pid, expiry)) if should_free(pid, expiry))
         });
-        let (offset, region) = out
-            .mem
-            .iter()
-            .enumerate()
-            .filter(|(_, region)| region.1 >= offset_mem_addr)
-            .next()
-            .unwrap();
-        out.offset = if region.1 as usize == self.offset {
-            offset
-        } else {
-            offset - 1
-        };
-        out
+        free_where(&mut self.mem, should_free);
+        self.free_list.sync(&self.mem);
+        if freed_in_front && self.options.rover == RoverPolicy::Reset {
+            self.rover = 0;
+        }
     }
 }
 
-impl MemAllocator for NextFit {
+impl MemAllocatorMut for NextFit {
     /// Handles a memory allocation request by adding it to the request queue.
-    fn request(&self, req: MemoryRequest) -> Self {
-        let mut out = self.clone();
-        out.reqs.push_back(req);
-        out
+    fn request(&mut self, req: MemoryRequest) {
+        self.align = self.align.max(req.align);
+        self.reqs.push_back(req.at_least_a_byte());
+    }
+
+    /// Advances the simulation by one time unit, processing deallocation
+    /// and request fulfillment.
+    fn tick(&mut self) {
+        self.time += 1;
+        self.searched = Search::default();
+        let time = self.time;
+        self.free_where(|_, expiry| expiry <= Lifetime::from(time));
+        self.fullfill_reqs();
+    }
+
+    fn free(&mut self, pid: Pid) {
+        self.free_where(|owner, _| owner == pid);
     }
 
-    /// Advances the simulation by one time unit, updating memory regions' lifetimes
-    /// and processing deallocation and request fulfillment.
-    /// 
-    /// Returns a tuple containing the current memory layout, processed requests, and
-    /// the updated state of the memory allocator.
-    fn tick(&self) -> (Vec<MemoryRegion>, Vec<MemoryRequest>, Self) {
-        let mut out = self.clone();
-        out.time += 1;
-        for i in out.mem.iter_mut() {
-            match i {
-                MemoryRegion(Some((pid, lifetime)), _) if *lifetime > 0 => *lifetime -= 1,
-                _ => {}
-            }
+    /// Compacts memory, everything moves so the rover goes back to the start.
+    fn compact(&mut self) -> Size {
+        let mem = compacted(&self.mem, self.align);
+        let before = std::mem::replace(&mut self.mem, mem);
+        self.free_list.sync(&self.mem);
+        self.rover = 0;
+        moved_bytes(&before, &self.mem)
+    }
+
+    /// Only a few processes move, so the rover stays where it is.
+    fn compact_for(&mut self, req: &MemoryRequest) -> Option<Size> {
+        let mem = partially_compacted(&self.mem, self.align, &self.options.rounded(req))?;
+        let before = std::mem::replace(&mut self.mem, mem);
+        self.free_list.sync(&self.mem);
+        Some(moved_bytes(&before, &self.mem))
+    }
+
+    /// The rover stays where it is, the same as with [`compact_for`](Self::compact_for).
+    fn compact_step(&mut self, budget: Size) -> (Size, Option<Addr>) {
+        let (mem, moved, next) = compacted_within(&self.mem, self.align, budget);
+        self.mem = mem;
+        self.free_list.sync(&self.mem);
+        (moved, next)
+    }
+
+    fn next_to_move(&self) -> Option<MemoryRegion> {
+        next_to_move(&self.mem, self.align)
+    }
+
+    fn name(&self) -> &'static str {
+        "Next Fit"
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            mem: self.mem.clone(),
+            reqs: self.reqs.clone(),
+            time: self.time,
+            slabs: Vector::new(),
+            rover: Some(self.rover),
+            moved: 0,
+            moving: None,
+            searched: self.searched,
+            classes: Vector::new(),
+            ends: None,
         }
-        let out = out.dealloc().fullfill_reqs();
-        (out.mem.clone(), out.reqs.clone().into_iter().collect(), out)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::strategies::Pid;
+    use crate::strategies::{AllocOptions, MemAllocator, MemoryRegion, MemoryRequest, Owner, Pid};
 
-    use super::*;
+    use super::{NextFit, RoverPolicy};
 
     /// I'm too lazy to do proper testing, but I wanna make sure it still
     /// works so I'll throw in a single unit test for your reading pleasure.
@@ -174,26 +242,29 @@ mod tests {
                 process: Pid(1),
                 size: 10,
                 lifetime: 5,
+                align: 1,
             })
             .request(MemoryRequest {
                 process: Pid(1),
                 size: 11,
                 lifetime: 5,
+                align: 1,
             })
             .request(MemoryRequest {
                 process: Pid(2),
                 size: 7,
                 lifetime: 5,
+                align: 1,
             });
         let (mem, _, _) = allocator.tick();
         assert_eq!(
             mem,
             vec![
-                MemoryRegion(Some((Pid(1), 5)), 0),
-                MemoryRegion(Some((Pid(1), 5)), 10),
-                MemoryRegion(Some((Pid(2), 5)), 21),
-                MemoryRegion(None, 28),
-                MemoryRegion(Some((Pid(FINAL_MEM_REGION_PID), -1)), 128)
+                MemoryRegion(Owner::Process(Pid(1), 5), 0),
+                MemoryRegion(Owner::Process(Pid(1), 5), 10),
+                MemoryRegion(Owner::Process(Pid(2), 5), 21),
+                MemoryRegion(Owner::Free, 28),
+                MemoryRegion(Owner::End, 128)
             ]
         )
     }
@@ -205,25 +276,28 @@ mod tests {
                 process: Pid(1),
                 size: 100,
                 lifetime: 5,
+                align: 1,
             })
             .request(MemoryRequest {
                 process: Pid(2),
                 size: 27,
                 lifetime: 5,
+                align: 1,
             })
             .request(MemoryRequest {
                 process: Pid(3),
                 size: 13,
                 lifetime: 5,
+                align: 1,
             });
         let (mem, _, _) = allocator.tick();
         assert_eq!(
             mem,
             vec![
-                MemoryRegion(Some((Pid(1), 5)), 0),
-                MemoryRegion(Some((Pid(2), 5)), 100),
-                MemoryRegion(None, 127),
-                MemoryRegion(Some((Pid(FINAL_MEM_REGION_PID), -1)), 128)
+                MemoryRegion(Owner::Process(Pid(1), 5), 0),
+                MemoryRegion(Owner::Process(Pid(2), 5), 100),
+                MemoryRegion(Owner::Free, 127),
+                MemoryRegion(Owner::End, 128)
             ]
         )
     }
@@ -237,11 +311,13 @@ mod tests {
                 process: Pid(1),
                 size: 10,
                 lifetime: 1,
+                align: 1,
             })
             .request(MemoryRequest {
                 process: Pid(2),
                 size: 7,
                 lifetime: 1,
+                align: 1,
             })
             .tick();
         assert_eq!(
@@ -250,14 +326,49 @@ mod tests {
                     process: Pid(3),
                     size: 3,
                     lifetime: 5,
+                    align: 1,
                 })
                 .tick()
                 .0,
             vec![
-                MemoryRegion(Some((Pid(3), 5)), 0),
-                MemoryRegion(None, 3),
-                MemoryRegion(Some((Pid(FINAL_MEM_REGION_PID), -1)), 128)
+                MemoryRegion(Owner::Process(Pid(3), 5), 0),
+                MemoryRegion(Owner::Free, 3),
+                MemoryRegion(Owner::End, 128)
             ]
         )
     }
+
+    #[test]
+    fn rover_policies() {
+        let req = |pid, size, lifetime| MemoryRequest {
+            process: Pid(pid),
+            size,
+            lifetime,
+            align: 1,
+        };
+        // where the third process goes, and where the rover ends up.
+        for (rover, expected) in [
+            (RoverPolicy::Stay, (20, 20)),
+            (RoverPolicy::Past, (20, 25)),
+            // the first process expiring frees memory in front of the rover.
+            (RoverPolicy::Reset, (0, 5)),
+        ] {
+            let options = AllocOptions {
+                rover,
+                ..AllocOptions::default()
+            };
+            let (_, _, alloc) = NextFit::new(128)
+                .with_options(options)
+                .request(req(1, 10, 1))
+                .request(req(2, 10, 5))
+                .tick();
+            let (mem, _, alloc) = alloc.request(req(3, 5, 5)).tick();
+            let placed = mem
+                .iter()
+                .find(|region| region.0 == Owner::Process(Pid(3), 5))
+                .unwrap()
+                .1;
+            assert_eq!((placed, alloc.rover()), expected, "{rover:?}");
+        }
+    }
 }
@@ -0,0 +1,178 @@
+use std::{fmt, sync::Arc};
+
+use super::{
+    fit::{Fit, FitPolicy, Hole, Holes},
+    MemoryRequest,
+};
+
+/// Scores how good of a home a hole is for a request, higher is better.
+/// `None` means the request shouldn't go in that hole at all.
+pub type ScoreFn = dyn Fn(&MemoryRequest, &Hole) -> Option<i64> + Send + Sync;
+
+/// Places each request in the hole with the best score from a closure,
+/// handy for trying out a heuristic without writing a whole new strategy.
+/// Ties go to the hole with the lowest address.
+///
+/// ```
+/// # use contiguous_memory_allocation::strategies::{Scored, ScoreFit};
+/// // best fit, but any hole within 10% of the request is good enough.
+/// let good_fit = ScoreFit::with_policy(
+///     1024,
+///     Scored::new("Good Fit", |req, hole| {
+///         let waste = (hole.size - req.size) as i64;
+///         Some(-waste.max(req.size as i64 / 10))
+///     }),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct Scored {
+    name: &'static str,
+    score: Arc<ScoreFn>,
+}
+
+impl Scored {
+    pub fn new(
+        name: &'static str,
+        score: impl Fn(&MemoryRequest, &Hole) -> Option<i64> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            score: Arc::new(score),
+        }
+    }
+}
+
+impl fmt::Debug for Scored {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scored").field("name", &self.name).finish()
+    }
+}
+
+impl FitPolicy for Scored {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn select(&mut self, req: &MemoryRequest, holes: &Holes) -> Option<Hole> {
+        holes
+            .iter()
+            .filter_map(|hole| Some(((self.score)(req, &hole)?, hole)))
+            .fold(None, |best, (score, hole)| match best {
+                Some((best_score, _)) if best_score >= score => best,
+                _ => Some((score, hole)),
+            })
+            .map(|(_, hole)| hole)
+    }
+}
+
+pub type ScoreFit = Fit<Scored>;
+
+#[cfg(test)]
+mod tests {
+    use crate::strategies::{MemAllocator, MemoryRegion, MemoryRequest, Owner, Pid};
+
+    use super::{ScoreFit, Scored};
+
+    #[test]
+    fn prefers_long_lived_neighbours() {
+        let mut allocator = ScoreFit::with_policy(
+            128,
+            Scored::new("Long Lived Neighbours", |_, hole| {
+                let lifetime = |region: Option<_>| match region {
+                    Some(MemoryRegion(Owner::Process(_, lifetime), _)) => lifetime as i64,
+                    _ => 0,
+                };
+                Some(lifetime(hole.prev) + lifetime(Some(hole.next)))
+            }),
+        );
+        allocator.set_layout(vec![
+            MemoryRegion(Owner::Process(Pid(0), 9), 0),
+            MemoryRegion(Owner::Free, 15), // gap of 6, next to long lived processes
+            MemoryRegion(Owner::Process(Pid(2), 9), 21),
+            MemoryRegion(Owner::Free, 22), // gap of 3
+            MemoryRegion(Owner::Process(Pid(3), 3), 25),
+            MemoryRegion(Owner::Free, 128),
+        ]);
+        assert_eq!(
+            allocator
+                .request(MemoryRequest {
+                    process: Pid(1),
+                    size: 3,
+                    lifetime: 3,
+                    align: 1,
+                })
+                .tick()
+                .0,
+            vec![
+                MemoryRegion(Owner::Process(Pid(0), 8), 0),
+                MemoryRegion(Owner::Process(Pid(1), 3), 15),
+                MemoryRegion(Owner::Free, 18),
+                MemoryRegion(Owner::Process(Pid(2), 8), 21),
+                MemoryRegion(Owner::Free, 22),
+                MemoryRegion(Owner::Process(Pid(3), 2), 25),
+                MemoryRegion(Owner::Free, 128),
+            ]
+        );
+    }
+
+    /// the neighbours' lifetimes are how long they have left, not when they
+    /// expire, so holes next to two processes don't win just because it's late.
+    #[test]
+    fn neighbours_have_time_left() {
+        let mut allocator = ScoreFit::with_policy(
+            128,
+            Scored::new("Long Lived Neighbours", |_, hole| {
+                let lifetime = |region: Option<_>| match region {
+                    Some(MemoryRegion(Owner::Process(_, lifetime), _)) => lifetime as i64,
+                    _ => 0,
+                };
+                Some(lifetime(hole.prev) + lifetime(Some(hole.next)))
+            }),
+        );
+        allocator.set_layout(vec![
+            MemoryRegion(Owner::Process(Pid(0), 103), 0),
+            MemoryRegion(Owner::Free, 15), // between two short lived processes
+            MemoryRegion(Owner::Process(Pid(2), 102), 21),
+            MemoryRegion(Owner::Process(Pid(3), 106), 25),
+            MemoryRegion(Owner::Free, 40), // after a long lived one
+            MemoryRegion(Owner::End, 128),
+        ]);
+        for _ in 0..100 {
+            (_, _, allocator) = allocator.tick();
+        }
+        assert_eq!(
+            allocator
+                .request(MemoryRequest {
+                    process: Pid(1),
+                    size: 3,
+                    lifetime: 3,
+                    align: 1,
+                })
+                .tick()
+                .0,
+            vec![
+                MemoryRegion(Owner::Process(Pid(0), 2), 0),
+                MemoryRegion(Owner::Free, 15),
+                MemoryRegion(Owner::Process(Pid(2), 1), 21),
+                MemoryRegion(Owner::Process(Pid(3), 5), 25),
+                MemoryRegion(Owner::Process(Pid(1), 3), 40),
+                MemoryRegion(Owner::Free, 43),
+                MemoryRegion(Owner::End, 128),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejected_holes_are_skipped() {
+        let (mem, reqs, _) = ScoreFit::with_policy(128, Scored::new("Never", |_, _| None))
+            .request(MemoryRequest {
+                process: Pid(1),
+                size: 3,
+                lifetime: 3,
+                align: 1,
+            })
+            .tick();
+        assert_eq!(mem.len(), 2);
+        assert_eq!(reqs.len(), 1);
+    }
+}
@@ -0,0 +1,159 @@
+use std::ops::Range;
+
+use super::{
+    fit::{Fit, FitPolicy, Hole, Holes},
+    MemoryRequest, Size,
+};
+
+/// How often requests of a size class found a hole in their own class.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub struct ClassStats {
+    /// the smallest size in the class, the class goes up to double this.
+    pub size: Size,
+    /// requests that were served straight from their own class.
+    pub hits: u64,
+    /// requests that had to look in bigger classes (and have a hole split
+    /// for them). Requests that are still waiting aren't counted yet.
+    pub misses: u64,
+}
+
+/// Segregated free lists (quick fit). Holes are sorted into power of
+/// two size classes, and a request takes the first hole (by address)
+/// in its own class that it fits in. If there isn't one it takes the
+/// first hole in the next biggest class that has any, which gets split.
+///
+/// The hole index is already ordered by size, so each class's list is
+/// just a range of it.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Segregated {
+    stats: Vec<ClassStats>,
+}
+
+/// which class a size is in, i.e. the power of two at or below it.
+fn class_of(size: Size) -> u32 {
+    size.max(1).ilog2()
+}
+
+/// the sizes in a class. Class 0 picks up empty requests too.
+fn class_sizes(class: u32) -> Range<Size> {
+    let smallest: Size = 1 << class;
+    let start = if class == 0 { 0 } else { smallest };
+    start..smallest.saturating_mul(2)
+}
+
+impl Segregated {
+    /// hits and misses for every class that's seen a request,
+    /// from the smallest class up.
+    pub fn stats(&self) -> Vec<ClassStats> {
+        self.stats
+            .iter()
+            .filter(|stats| stats.hits + stats.misses > 0)
+            .copied()
+            .collect()
+    }
+
+    fn class_stats(&mut self, class: u32) -> &mut ClassStats {
+        let class = class as usize;
+        if self.stats.len() <= class {
+            self.stats.resize(class + 1, ClassStats::default());
+            for (i, stats) in self.stats.iter_mut().enumerate() {
+                stats.size = 1 << i;
+            }
+        }
+        &mut self.stats[class]
+    }
+}
+
+impl FitPolicy for Segregated {
+    fn name(&self) -> &'static str {
+        "Segregated Fit"
+    }
+
+    fn select(&mut self, req: &MemoryRequest, holes: &Holes) -> Option<Hole> {
+        (class_of(req.size)..Size::BITS).find_map(|class| holes.first_in(class_sizes(class)))
+    }
+
+    fn placed(&mut self, req: &MemoryRequest, hole: &Hole) {
+        let class = class_of(req.size);
+        let stats = self.class_stats(class);
+        if class_sizes(class).contains(&hole.size) {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+    }
+
+    fn classes(&self) -> Vec<ClassStats> {
+        self.stats()
+    }
+}
+
+pub type SegregatedFit = Fit<Segregated>;
+
+#[cfg(test)]
+mod tests {
+    use crate::strategies::{MemAllocatorMut, MemoryRegion, MemoryRequest, Owner, Pid};
+
+    use super::{ClassStats, SegregatedFit};
+
+    #[test]
+    fn falls_back_to_bigger_classes() {
+        let req = |pid, size| MemoryRequest {
+            process: Pid(pid),
+            size,
+            lifetime: 5,
+            align: 1,
+        };
+        let mut alloc = SegregatedFit::new(64);
+        for (pid, size) in [(1, 20), (2, 8), (3, 8), (4, 28)] {
+            alloc.request(req(pid, size));
+        }
+        alloc.tick();
+        // leaves holes of 20 at 0 and 8 at 28.
+        alloc.free(Pid(1));
+        alloc.free(Pid(3));
+        // the 8 goes in the hole in its own class, even though the 20
+        // comes first. Nothing's left in 4-7 or 8-15 for the 6 though.
+        alloc.request(req(5, 8));
+        alloc.request(req(6, 6));
+        alloc.tick();
+        assert_eq!(
+            alloc.snapshot().layout(),
+            vec![
+                MemoryRegion(Owner::Process(Pid(6), 5), 0),
+                MemoryRegion(Owner::Free, 6),
+                MemoryRegion(Owner::Process(Pid(2), 4), 20),
+                MemoryRegion(Owner::Process(Pid(5), 5), 28),
+                MemoryRegion(Owner::Process(Pid(4), 4), 36),
+                MemoryRegion(Owner::End, 64),
+            ]
+        );
+        // one that can't fit anywhere isn't counted
+        // however many ticks it spends waiting.
+        alloc.request(req(7, 40));
+        alloc.tick();
+        alloc.tick();
+        // the first tick mostly had to split up the rest of memory.
+        assert_eq!(alloc.snapshot().classes(), alloc.policy().stats());
+        assert_eq!(
+            alloc.policy().stats(),
+            vec![
+                ClassStats {
+                    size: 4,
+                    hits: 0,
+                    misses: 1
+                },
+                ClassStats {
+                    size: 8,
+                    hits: 1,
+                    misses: 2
+                },
+                ClassStats {
+                    size: 16,
+                    hits: 1,
+                    misses: 1
+                },
+            ]
+        );
+    }
+}
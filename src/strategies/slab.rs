@@ -0,0 +1,409 @@
+use im::Vector;
+
+use super::{
+    expires_at, serve_queue, Addr, AllocOptions, Backing, Best, DynMemAllocator, First, Fit,
+    FitPolicy, Lifetime, MemAllocatorMut, MemoryRegion, MemoryRequest, Owner, Pid, Segregated,
+    Size, Snapshot, Worst,
+};
+
+/// the smallest objects slabs are made for, anything
+/// smaller gets one of these anyway.
+const MIN_OBJECT: Size = 8;
+
+/// How [`Slab`] splits up requests.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct SlabOptions {
+    /// requests up to this big come out of slabs.
+    pub threshold: Size,
+    /// how big each slab is.
+    pub size: Size,
+    /// what big requests and the slabs themselves go into.
+    pub backing: Backing,
+}
+
+impl Default for SlabOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 8 * 1024,
+            size: 64 * 1024,
+            backing: Backing::default(),
+        }
+    }
+}
+
+/// How full a slab is, for showing people.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct SlabInfo {
+    /// how big the slab's objects are.
+    pub object_size: Size,
+    /// how many objects fit in the slab.
+    pub slots: u32,
+    /// how many of those are in use.
+    pub used: u32,
+    /// what's lost to objects being smaller than their slot, and to
+    /// the end of the slab that isn't big enough for another one.
+    pub waste: Size,
+}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+struct SlabState {
+    start: Addr,
+    object_size: Size,
+    /// the process in each slot, and the tick it expires at.
+    slots: Vector<Option<(MemoryRequest, Lifetime)>>,
+}
+
+impl SlabState {
+    fn info(&self, slab_size: Size) -> SlabInfo {
+        let used = self.slots.iter().flatten().collect::<Vec<_>>();
+        SlabInfo {
+            object_size: self.object_size,
+            slots: self.slots.len() as u32,
+            used: used.len() as u32,
+            waste: slab_size - self.slots.len() as Size * self.object_size
+                + used
+                    .iter()
+                    .map(|(req, _)| self.object_size - req.size)
+                    .sum::<Size>(),
+        }
+    }
+
+    /// the slab laid out like the rest of memory. Empty slots and the
+    /// end of the slab are [`Owner::Slab`], objects are processes with
+    /// whatever they don't use of their slot as padding.
+    fn layout(&self, slab_size: Size) -> Vec<MemoryRegion> {
+        let mut out: Vec<MemoryRegion> = vec![];
+        let empty = Owner::Slab(self.object_size);
+        for (i, slot) in self.slots.iter().enumerate() {
+            let addr = self.start + i as Size * self.object_size;
+            match slot {
+                Some((req, expiry)) => {
+                    out.push(MemoryRegion(Owner::Process(req.process, *expiry), addr));
+                    if req.size < self.object_size {
+                        out.push(MemoryRegion(
+                            Owner::Padding(req.process, *expiry),
+                            addr + req.size,
+                        ));
+                    }
+                }
+                None if out.last().map(|region| region.0) != Some(empty) => {
+                    out.push(MemoryRegion(empty, addr))
+                }
+                None => {}
+            }
+        }
+        let end = self.start + self.slots.len() as Size * self.object_size;
+        if end < self.start + slab_size && out.last().map(|region| region.0) != Some(empty) {
+            out.push(MemoryRegion(empty, end));
+        }
+        out
+    }
+}
+
+/// A slab allocator. Small requests are rounded up to a power of two and
+/// served from slabs, which are big blocks cut up into slots all the same
+/// size. Slabs are only ever given back once they're empty, so lots of
+/// small, short lived processes don't leave holes all over memory.
+/// Anything bigger than the threshold goes to the backing strategy, which
+/// is also where the slabs are put.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Slab<P> {
+    backing: Fit<P>,
+    /// in address order.
+    slabs: Vector<SlabState>,
+    /// small requests waiting for a slot.
+    reqs: Vector<MemoryRequest>,
+    time: u32,
+    options: AllocOptions,
+}
+
+impl<P: FitPolicy + Default> Slab<P> {
+    pub fn new(mem_size: Size) -> Self {
+        Self {
+            backing: Fit::new(mem_size),
+            slabs: Vector::new(),
+            reqs: Vector::new(),
+            time: 0,
+            options: AllocOptions::default(),
+        }
+    }
+}
+
+impl<P: FitPolicy> Slab<P> {
+    pub fn with_options(mut self, options: AllocOptions) -> Self {
+        self.backing = self.backing.with_options(options.clone());
+        self.options = options;
+        self
+    }
+
+    /// how full each slab is, in address order.
+    pub fn slabs(&self) -> Vec<SlabInfo> {
+        self.slabs
+            .iter()
+            .map(|slab| slab.info(self.options.slabs.size))
+            .collect()
+    }
+
+    /// the size of slot the request would get, if it's small enough for one.
+    fn object_size(&self, req: &MemoryRequest) -> Option<Size> {
+        let size = self.options.granted(req.size);
+        let object_size = size.next_power_of_two().max(MIN_OBJECT);
+        // slots start at multiples of their size,
+        // so that's as far as they can line things up.
+        (size <= self.options.slabs.threshold
+            && object_size <= self.options.slabs.size
+            && req.align <= object_size)
+            .then_some(object_size)
+    }
+
+    fn fullfill_reqs(&mut self) {
+        self.reqs = serve_queue(self.reqs.clone(), |req| self.place(*req));
+    }
+
+    /// Puts the request in the first free slot of its size,
+    /// making a new slab for it if they're all full.
+    fn place(&mut self, req: MemoryRequest) -> bool {
+        let object_size = self
+            .object_size(&req)
+            .expect("only small requests wait for a slot");
+        let holder = Some((req, expires_at(self.time, req.lifetime)));
+        for slab in self.slabs.iter_mut() {
+            if slab.object_size != object_size {
+                continue;
+            }
+            if let Some(slot) = slab.slots.iter_mut().find(|slot| slot.is_none()) {
+                *slot = holder;
+                return true;
+            }
+        }
+        let slab_size = self.options.slabs.size;
+        let Some(start) = self
+            .backing
+            .reserve(slab_size, object_size, Owner::Slab(object_size))
+        else {
+            return false;
+        };
+        let mut slots = Vector::from(vec![None; (slab_size / object_size) as usize]);
+        slots[0] = holder;
+        let index = self
+            .slabs
+            .iter()
+            .take_while(|slab| slab.start < start)
+            .count();
+        self.slabs.insert(
+            index,
+            SlabState {
+                start,
+                object_size,
+                slots,
+            },
+        );
+        true
+    }
+
+    /// Catches the slabs up with wherever compacting moved them to.
+    /// They never change order, so they're matched up one by one.
+    fn follow_slabs(&mut self) {
+        let starts = self
+            .backing
+            .mem
+            .iter()
+            .filter(|region| matches!(region.0, Owner::Slab(_)))
+            .map(|region| region.1);
+        for (slab, start) in self.slabs.iter_mut().zip(starts) {
+            slab.start = start;
+        }
+    }
+
+    fn free_where(&mut self, should_free: impl Fn(Pid, Lifetime) -> bool) {
+        for slab in self.slabs.iter_mut() {
+            for slot in slab.slots.iter_mut() {
+                if matches!(slot, Some((req, expiry)) if should_free(req.process, *expiry)) {
+                    *slot = None;
+                }
+            }
+        }
+        // give empty slabs back so the memory can go to anyone.
+        for slab in self.slabs.iter() {
+            if slab.slots.iter().all(Option::is_none) {
+                self.backing.release(slab.start);
+            }
+        }
+        self.slabs
+            .retain(|slab| slab.slots.iter().any(Option::is_some));
+    }
+}
+
+impl<P: FitPolicy> MemAllocatorMut for Slab<P> {
+    fn request(&mut self, req: MemoryRequest) {
+        let req = req.at_least_a_byte();
+        if self.object_size(&req).is_some() {
+            self.reqs.push_back(req);
+        } else {
+            self.backing.request(req);
+        }
+    }
+
+    /// frees whatever expired, then serves the big
+    /// requests before the small ones.
+    fn tick(&mut self) {
+        self.time += 1;
+        let time = self.time;
+        self.free_where(|_, expiry| expiry <= Lifetime::from(time));
+        self.backing.tick();
+        self.fullfill_reqs();
+    }
+
+    fn free(&mut self, pid: Pid) {
+        self.free_where(|owner, _| owner == pid);
+        self.backing.free(pid);
+    }
+
+    /// Slabs move along with everything else.
+    fn compact(&mut self) -> Size {
+        let moved = self.backing.compact();
+        self.follow_slabs();
+        moved
+    }
+
+    /// A small request needs room for a whole new slab.
+    fn compact_for(&mut self, req: &MemoryRequest) -> Option<Size> {
+        let moved = match self.object_size(req) {
+            Some(object_size) => self.backing.compact_for(&MemoryRequest {
+                size: self.options.slabs.size,
+                align: object_size,
+                ..*req
+            }),
+            None => self.backing.compact_for(req),
+        }?;
+        self.follow_slabs();
+        Some(moved)
+    }
+
+    fn compact_step(&mut self, budget: Size) -> (Size, Option<Addr>) {
+        let step = self.backing.compact_step(budget);
+        self.follow_slabs();
+        step
+    }
+
+    fn next_to_move(&self) -> Option<MemoryRegion> {
+        self.backing.next_to_move()
+    }
+
+    fn name(&self) -> &'static str {
+        "Slab"
+    }
+
+    /// The backing strategy's memory, with each slab filled in.
+    fn snapshot(&self) -> Snapshot {
+        let backing = self.backing.snapshot();
+        let mut slabs = self.slabs.iter();
+        let mut mem = Vector::new();
+        for region in backing.mem {
+            match region.0 {
+                Owner::Slab(_) => {
+                    let slab = slabs.next().expect("every slab is in memory");
+                    mem.extend(slab.layout(self.options.slabs.size));
+                }
+                _ => mem.push_back(region),
+            }
+        }
+        Snapshot {
+            mem,
+            reqs: backing.reqs + self.reqs.clone(),
+            time: self.time,
+            slabs: self.slabs().into(),
+            rover: None,
+            moved: 0,
+            moving: None,
+            searched: backing.searched,
+            classes: backing.classes,
+            ends: backing.ends,
+        }
+    }
+}
+
+/// A [`Slab`] allocator with whichever backing strategy the options ask for.
+pub(super) fn with_backing(mem_size: Size, options: AllocOptions) -> Box<dyn DynMemAllocator> {
+    match options.slabs.backing {
+        Backing::Best => Box::new(Slab::<Best>::new(mem_size).with_options(options)),
+        Backing::First => Box::new(Slab::<First>::new(mem_size).with_options(options)),
+        Backing::Worst => Box::new(Slab::<Worst>::new(mem_size).with_options(options)),
+        Backing::Segregated => Box::new(Slab::<Segregated>::new(mem_size).with_options(options)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::strategies::{
+        AllocOptions, Best, MemAllocatorMut, MemoryRegion, MemoryRequest, Owner, Pid, SlabInfo,
+        SlabOptions,
+    };
+
+    use super::Slab;
+
+    #[test]
+    fn small_requests_share_a_slab() {
+        let req = |pid, size| MemoryRequest {
+            process: Pid(pid),
+            size,
+            lifetime: pid,
+            align: 1,
+        };
+        let mut alloc = Slab::<Best>::new(256).with_options(AllocOptions {
+            slabs: SlabOptions {
+                threshold: 16,
+                size: 56,
+                ..SlabOptions::default()
+            },
+            ..AllocOptions::default()
+        });
+        for (pid, size) in [(1, 10), (2, 100), (3, 16), (4, 5)] {
+            alloc.request(req(pid, size));
+        }
+        alloc.tick();
+        assert_eq!(
+            alloc.snapshot().layout(),
+            vec![
+                MemoryRegion(Owner::Process(Pid(2), 2), 0),
+                // slabs start at a multiple of their object size.
+                MemoryRegion(Owner::Free, 100),
+                // 10 and 16 both get 16 byte slots, only 3 fit.
+                MemoryRegion(Owner::Process(Pid(1), 1), 112),
+                MemoryRegion(Owner::Padding(Pid(1), 1), 122),
+                MemoryRegion(Owner::Process(Pid(3), 3), 128),
+                MemoryRegion(Owner::Slab(16), 144),
+                MemoryRegion(Owner::Process(Pid(4), 4), 168),
+                MemoryRegion(Owner::Padding(Pid(4), 4), 173),
+                MemoryRegion(Owner::Slab(8), 176),
+                MemoryRegion(Owner::Free, 224),
+                MemoryRegion(Owner::End, 256),
+            ]
+        );
+        assert_eq!(
+            alloc.slabs(),
+            vec![
+                SlabInfo {
+                    object_size: 16,
+                    slots: 3,
+                    used: 2,
+                    waste: 14,
+                },
+                SlabInfo {
+                    object_size: 8,
+                    slots: 7,
+                    used: 1,
+                    waste: 3,
+                },
+            ]
+        );
+        // slabs are given back once they're empty.
+        for _ in 0..4 {
+            alloc.tick();
+        }
+        assert_eq!(
+            alloc.snapshot().layout(),
+            vec![MemoryRegion(Owner::Free, 0), MemoryRegion(Owner::End, 256),]
+        );
+    }
+}
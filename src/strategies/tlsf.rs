@@ -0,0 +1,521 @@
+use std::ops::Range;
+
+use im::{OrdMap, OrdSet, Vector};
+
+use super::{
+    allocate, compacted, compacted_within, expires_at, moved_bytes, next_to_move, padding_in,
+    partially_compacted, serve_queue, Addr, AllocOptions, Lifetime, MemAllocatorMut, MemoryRegion,
+    MemoryRequest, Owner, Pid, Search, Size, Snapshot,
+};
+
+/// how many bits of a size pick its second level list, so every
+/// power of two range gets split into 16 lists.
+const SL_BITS: u32 = 4;
+const SL_COUNT: u32 = 1 << SL_BITS;
+
+/// the list a hole of this size goes in. The first level is the power of two
+/// at or below the size, the second is which 16th of that range it's in.
+fn mapping(size: Size) -> (u32, u32) {
+    let size = size.max(1);
+    let fl = size.ilog2();
+    let sl = if fl >= SL_BITS {
+        size >> (fl - SL_BITS)
+    } else {
+        size << (SL_BITS - fl)
+    };
+    (fl, sl as u32 ^ SL_COUNT)
+}
+
+/// the first list where every hole is big enough for the size, i.e.
+/// the size rounded up to the start of the next list.
+fn mapping_search(size: Size) -> (u32, u32) {
+    let size = size.max(1);
+    let fl = size.ilog2();
+    if fl >= SL_BITS {
+        mapping(size.saturating_add((1 << (fl - SL_BITS)) - 1))
+    } else {
+        mapping(size)
+    }
+}
+
+/// The free lists, with a bit set for every one that has a hole in it.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+struct FreeLists {
+    /// which first level ranges have any holes.
+    fl: u64,
+    /// which second level lists have any holes, per first level.
+    sl: [u32; Size::BITS as usize],
+    lists: OrdMap<(u32, u32), OrdSet<Addr>>,
+}
+
+impl FreeLists {
+    fn from_layout(mem: &Vector<MemoryRegion>) -> Self {
+        let mut out = Self {
+            fl: 0,
+            sl: [0; Size::BITS as usize],
+            lists: OrdMap::new(),
+        };
+        for (region, next) in mem.iter().zip(mem.iter().skip(1)) {
+            if let MemoryRegion(Owner::Free, start) = *region {
+                out.insert(start, next.1 - start);
+            }
+        }
+        out
+    }
+
+    fn insert(&mut self, addr: Addr, size: Size) {
+        let (fl, sl) = mapping(size);
+        self.lists.entry((fl, sl)).or_default().insert(addr);
+        self.fl |= 1 << fl;
+        self.sl[fl as usize] |= 1 << sl;
+    }
+
+    fn remove(&mut self, addr: Addr, size: Size) {
+        let (fl, sl) = mapping(size);
+        let Some(addrs) = self.lists.get_mut(&(fl, sl)) else {
+            return;
+        };
+        addrs.remove(&addr);
+        if addrs.is_empty() {
+            self.lists.remove(&(fl, sl));
+            self.sl[fl as usize] &= !(1 << sl);
+            if self.sl[fl as usize] == 0 {
+                self.fl &= !(1 << fl);
+            }
+        }
+    }
+
+    /// the address of a hole that's at least `size` big. This only ever
+    /// looks at two bitmaps, no matter how many holes there are.
+    fn find(&self, size: Size) -> Option<Addr> {
+        let (mut fl, sl) = mapping_search(size);
+        let mut bits = self.sl[fl as usize] & (!0 << sl);
+        if bits == 0 {
+            let fls = self.fl & (!0u64).checked_shl(fl + 1).unwrap_or(0);
+            if fls == 0 {
+                return None;
+            }
+            fl = fls.trailing_zeros();
+            bits = self.sl[fl as usize];
+        }
+        self.lists[&(fl, bits.trailing_zeros())].get_min().copied()
+    }
+}
+
+/// Every process in memory, by when it expires and by who it belongs
+/// to, so freeing one doesn't mean looking through all of memory.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+struct Allocations {
+    /// who each allocation belongs to, when it expires and where it ends,
+    /// by where it starts. It covers the process and its padding and
+    /// rounding, and nothing else, even if the same process (with the
+    /// same expiry) has the memory right after it too.
+    by_start: OrdMap<Addr, (Pid, Lifetime, Addr)>,
+    /// when each allocation expires, and where it starts.
+    by_expiry: OrdSet<(Lifetime, Addr)>,
+    /// where each of a pid's allocations starts.
+    by_pid: OrdMap<u32, OrdSet<Addr>>,
+}
+
+impl Allocations {
+    /// everything held by processes in memory. Padding in front of a
+    /// process is where it starts, and whatever the process holds after
+    /// it is where it ends. Two allocations of a process that expire at the
+    /// same time might be split up differently to how they were placed,
+    /// but they're freed at the same time anyway.
+    fn from_layout(mem: &Vector<MemoryRegion>) -> Self {
+        let mut out = Self::default();
+        // who's holding the allocation so far, where it starts, and
+        // whether its process has been seen yet.
+        let mut current: Option<((Pid, Lifetime), Addr, bool)> = None;
+        for (region, next) in mem.iter().zip(mem.iter().skip(1)) {
+            let held_by = region.0.held_by();
+            let starts_process = match region.0 {
+                Owner::Process(..) => true,
+                Owner::Padding(..) => next.0.is_process() && next.0.held_by() == held_by,
+                _ => false,
+            };
+            let continues = current.is_some_and(|(holder, _, seen)| {
+                Some(holder) == held_by && !(seen && starts_process)
+            });
+            if !continues {
+                if let Some(((pid, expiry), start, _)) = current.take() {
+                    out.insert(pid, expiry, start..region.1);
+                }
+                current = held_by.map(|holder| (holder, region.1, false));
+            }
+            if let Some((_, _, seen)) = &mut current {
+                *seen |= region.0.is_process();
+            }
+        }
+        if let Some(((pid, expiry), start, _)) = current {
+            let end = mem.last().expect("memory always has an end").1;
+            out.insert(pid, expiry, start..end);
+        }
+        out
+    }
+
+    fn insert(&mut self, pid: Pid, expiry: Lifetime, range: Range<Addr>) {
+        self.by_start.insert(range.start, (pid, expiry, range.end));
+        self.by_expiry.insert((expiry, range.start));
+        self.by_pid.entry(pid.0).or_default().insert(range.start);
+    }
+
+    /// takes out the allocation starting at `addr`, returning
+    /// who it belonged to, when it expired and where it ended.
+    fn remove(&mut self, addr: Addr) -> Option<(Pid, Lifetime, Addr)> {
+        let (pid, expiry, end) = self.by_start.remove(&addr)?;
+        self.by_expiry.remove(&(expiry, addr));
+        if let Some(addrs) = self.by_pid.get_mut(&pid.0) {
+            addrs.remove(&addr);
+            if addrs.is_empty() {
+                self.by_pid.remove(&pid.0);
+            }
+        }
+        Some((pid, expiry, end))
+    }
+
+    /// where the process that expires first starts, if it's expired by `time`.
+    fn expired(&self, time: u32) -> Option<Addr> {
+        self.by_expiry
+            .get_min()
+            .filter(|(expiry, _)| *expiry <= Lifetime::from(time))
+            .map(|(_, addr)| *addr)
+    }
+}
+
+/// Two-level segregated fit. Holes are kept in lists by size, and two
+/// levels of bitmaps say which lists have anything in them, so finding
+/// a hole takes the same time however fragmented memory gets. That's
+/// what makes it a good fit for real-time systems.
+///
+/// The catch is it rounds the search up to the next list, so it might
+/// skip over a hole that would've fit if it's in a list with holes that
+/// wouldn't. It's a good fit rather than the best fit.
+///
+/// The lists are kept up to date as holes are split and merged, so
+/// placing or freeing a process only touches a few of them. Compacting
+/// moves everything anyway, so the lists are just built again after it.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Tlsf {
+    reqs: Vector<MemoryRequest>,
+    mem: Vector<MemoryRegion>,
+    lists: FreeLists,
+    allocations: Allocations,
+    time: u32,
+    /// the biggest alignment anything's asked for, see [`compacted`].
+    align: Size,
+    /// how much looking for holes placing things on this tick took.
+    searched: Search,
+    options: AllocOptions,
+}
+
+impl Tlsf {
+    pub fn new(mem_size: Size) -> Self {
+        let mem = Vector::from(vec![
+            MemoryRegion(Owner::Free, 0),
+            MemoryRegion(Owner::End, mem_size),
+        ]);
+        Self {
+            reqs: Vector::new(),
+            lists: FreeLists::from_layout(&mem),
+            allocations: Allocations::default(),
+            mem,
+            time: 0,
+            align: 1,
+            searched: Search::default(),
+            options: AllocOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: AllocOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    fn fullfill_reqs(&mut self) {
+        self.reqs = serve_queue(self.reqs.clone(), |req| self.place(*req));
+    }
+
+    /// Places a single request, returning false if there's no hole for it.
+    fn place(&mut self, req: MemoryRequest) -> bool {
+        let rounded = self.options.rounded(&req);
+        // asking for enough extra to line it up wherever
+        // the hole starts means any hole we find will do.
+        let found = self
+            .lists
+            .find(rounded.size.saturating_add(rounded.align.max(1) - 1));
+        // it's only ever the hole at the front of one list.
+        self.searched.record(u64::from(found.is_some()));
+        let Some(addr) = found else {
+            return false;
+        };
+        let index = self
+            .mem
+            .binary_search_by_key(&addr, |region| region.1)
+            .expect("the free lists are out of sync with memory");
+        let size = self.mem[index + 1].1 - addr;
+        let Some(pad) = padding_in(&rounded, addr, size) else {
+            return false;
+        };
+        self.lists.remove(addr, size);
+        let mut end = addr + size;
+        let leftover = size - pad - rounded.size;
+        if self.options.keeps(leftover) {
+            end -= leftover;
+            self.lists.insert(end, leftover);
+        }
+        let expiry = expires_at(self.time, req.lifetime);
+        allocate(&mut self.mem, index, pad, &req, expiry, &self.options);
+        self.allocations.insert(req.process, expiry, addr..end);
+        true
+    }
+
+    /// Frees the allocation (the process and its padding) starting at `addr`,
+    /// merging it with the holes either side of it. Only those holes' lists
+    /// change.
+    fn release(&mut self, addr: Addr) {
+        let (_, _, end) = self
+            .allocations
+            .remove(addr)
+            .expect("only allocations are released");
+        let index_of = |addr| {
+            self.mem
+                .binary_search_by_key(&addr, |region: &MemoryRegion| region.1)
+                .expect("the allocations are out of sync with memory")
+        };
+        let mut start = index_of(addr);
+        let mut end = index_of(end);
+        if start > 0 && self.mem[start - 1].0.is_free() {
+            start -= 1;
+            self.lists
+                .remove(self.mem[start].1, self.mem[start + 1].1 - self.mem[start].1);
+        }
+        if self.mem[end].0.is_free() {
+            self.lists
+                .remove(self.mem[end].1, self.mem[end + 1].1 - self.mem[end].1);
+            end += 1;
+        }
+        let hole = self.mem[start].1;
+        self.lists.insert(hole, self.mem[end].1 - hole);
+        self.mem[start].0 = Owner::Free;
+        for _ in start + 1..end {
+            self.mem.remove(start + 1);
+        }
+    }
+
+    /// Builds the lists and the allocations again from scratch,
+    /// for after something's moved everything around.
+    fn rebuild(&mut self) {
+        self.lists = FreeLists::from_layout(&self.mem);
+        self.allocations = Allocations::from_layout(&self.mem);
+    }
+}
+
+impl MemAllocatorMut for Tlsf {
+    fn request(&mut self, req: MemoryRequest) {
+        self.align = self.align.max(req.align);
+        self.reqs.push_back(req.at_least_a_byte());
+    }
+
+    fn tick(&mut self) {
+        self.time += 1;
+        self.searched = Search::default();
+        while let Some(addr) = self.allocations.expired(self.time) {
+            self.release(addr);
+        }
+        self.fullfill_reqs();
+    }
+
+    fn free(&mut self, pid: Pid) {
+        let addrs = self
+            .allocations
+            .by_pid
+            .get(&pid.0)
+            .cloned()
+            .unwrap_or_default();
+        for addr in addrs {
+            self.release(addr);
+        }
+    }
+
+    fn compact(&mut self) -> Size {
+        let mem = compacted(&self.mem, self.align);
+        let before = std::mem::replace(&mut self.mem, mem);
+        self.rebuild();
+        moved_bytes(&before, &self.mem)
+    }
+
+    fn compact_for(&mut self, req: &MemoryRequest) -> Option<Size> {
+        let mem = partially_compacted(&self.mem, self.align, &self.options.rounded(req))?;
+        let before = std::mem::replace(&mut self.mem, mem);
+        self.rebuild();
+        Some(moved_bytes(&before, &self.mem))
+    }
+
+    fn compact_step(&mut self, budget: Size) -> (Size, Option<Addr>) {
+        let (mem, moved, next) = compacted_within(&self.mem, self.align, budget);
+        self.mem = mem;
+        self.rebuild();
+        (moved, next)
+    }
+
+    fn next_to_move(&self) -> Option<MemoryRegion> {
+        next_to_move(&self.mem, self.align)
+    }
+
+    fn name(&self) -> &'static str {
+        "TLSF"
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            mem: self.mem.clone(),
+            reqs: self.reqs.clone(),
+            time: self.time,
+            slabs: Vector::new(),
+            rover: None,
+            moved: 0,
+            moving: None,
+            searched: self.searched,
+            classes: Vector::new(),
+            ends: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::strategies::{MemAllocatorMut, MemoryRegion, MemoryRequest, Owner, Pid};
+
+    use super::{Allocations, FreeLists, Tlsf};
+
+    /// splitting and merging holes in place should leave the lists the same
+    /// as building them from scratch, and no request should ever have to
+    /// look at more than one hole.
+    #[test]
+    fn lists_stay_in_sync() {
+        let mut rng = StdRng::seed_from_u64(44);
+        let mut alloc = Tlsf::new(4096);
+        for i in 0..300 {
+            for pid in i * 3..i * 3 + 3 {
+                alloc.request(MemoryRequest {
+                    process: Pid(pid),
+                    size: rng.gen_range(0..256),
+                    lifetime: rng.gen_range(0..8),
+                    align: 1 << rng.gen_range(0..4),
+                });
+            }
+            if i % 10 == 9 {
+                alloc.free(Pid(i * 3 - 20));
+            }
+            alloc.tick();
+            assert_eq!(alloc.lists, FreeLists::from_layout(&alloc.mem), "tick {i}");
+            assert_eq!(
+                alloc.allocations,
+                Allocations::from_layout(&alloc.mem),
+                "tick {i}"
+            );
+            assert!(alloc.snapshot().searched().longest <= 1);
+        }
+    }
+
+    /// two allocations of the same process that expire together and end up
+    /// next to each other are still freed one at a time.
+    #[test]
+    fn same_process_side_by_side() {
+        let req = |pid, size, lifetime| MemoryRequest {
+            process: Pid(pid),
+            size,
+            lifetime,
+            align: 1,
+        };
+        for free in [false, true] {
+            let mut alloc = Tlsf::new(128);
+            for (pid, size, lifetime) in [(1, 10, 2), (1, 11, 2), (2, 7, 5)] {
+                alloc.request(req(pid, size, lifetime));
+            }
+            alloc.tick();
+            assert_eq!(alloc.allocations.by_start.len(), 3);
+            if free {
+                alloc.free(Pid(1));
+            } else {
+                alloc.tick();
+                alloc.tick();
+            }
+            assert_eq!(alloc.lists, FreeLists::from_layout(&alloc.mem));
+            assert_eq!(alloc.allocations, Allocations::from_layout(&alloc.mem));
+            assert_eq!(
+                alloc.mem.iter().copied().collect::<Vec<_>>(),
+                vec![
+                    MemoryRegion(Owner::Free, 0),
+                    MemoryRegion(Owner::Process(Pid(2), 6), 21),
+                    MemoryRegion(Owner::Free, 28),
+                    MemoryRegion(Owner::End, 128),
+                ]
+            );
+        }
+    }
+
+    /// a process that asks for nothing still gets a region of its own, so
+    /// freeing it can't be mixed up with the hole after it.
+    #[test]
+    fn empty_requests() {
+        let mut alloc = Tlsf::new(64);
+        for (pid, size, lifetime) in [(1, 0, 1), (2, 0, 3), (3, 4, 1)] {
+            alloc.request(MemoryRequest {
+                process: Pid(pid),
+                size,
+                lifetime,
+                align: 1,
+            });
+        }
+        for _ in 0..4 {
+            alloc.tick();
+            assert_eq!(alloc.lists, FreeLists::from_layout(&alloc.mem));
+            assert_eq!(alloc.allocations, Allocations::from_layout(&alloc.mem));
+        }
+        assert_eq!(
+            alloc.snapshot().layout(),
+            vec![MemoryRegion(Owner::Free, 0), MemoryRegion(Owner::End, 64)]
+        );
+    }
+
+    #[test]
+    fn good_fit_not_best_fit() {
+        let req = |pid, size| MemoryRequest {
+            process: Pid(pid),
+            size,
+            lifetime: 5,
+            align: 1,
+        };
+        let mut alloc = Tlsf::new(256);
+        for (pid, size) in [(1, 100), (2, 10), (3, 41), (4, 100)] {
+            alloc.request(req(pid, size));
+        }
+        alloc.tick();
+        // leaves holes of 100 at 0 and 41 at 110.
+        alloc.free(Pid(1));
+        alloc.free(Pid(3));
+        // the 41 skips the 41 byte hole, since 40 byte holes go in the
+        // same list and it'd have to check. The 40 can use it though.
+        alloc.request(req(6, 41));
+        alloc.request(req(5, 40));
+        alloc.tick();
+        assert_eq!(
+            alloc.snapshot().layout(),
+            vec![
+                MemoryRegion(Owner::Process(Pid(6), 5), 0),
+                MemoryRegion(Owner::Free, 41),
+                MemoryRegion(Owner::Process(Pid(2), 4), 100),
+                MemoryRegion(Owner::Process(Pid(5), 5), 110),
+                MemoryRegion(Owner::Free, 150),
+                MemoryRegion(Owner::Process(Pid(4), 4), 151),
+                MemoryRegion(Owner::Free, 251),
+                MemoryRegion(Owner::End, 256),
+            ]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use im::Vector;
+
+use super::{
+    fit::{Backing, Fit, FitPolicy, Hole, Holes},
+    AllocOptions, Best, ClassStats, DynMemAllocator, First, MemoryRegion, MemoryRequest, Owner,
+    Segregated, Size, Worst,
+};
+
+/// How [`TwoEnded`] splits up requests.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct TwoEndedOptions {
+    /// requests this big or bigger go at the top of memory.
+    pub threshold: Size,
+    /// what picks the hole for each request.
+    pub base: Backing,
+}
+
+impl Default for TwoEndedOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 64 * 1024,
+            base: Backing::default(),
+        }
+    }
+}
+
+/// Keeps small and big processes apart, with small ones at the bottom of
+/// memory and big ones at the top. The base policy still picks the hole,
+/// this only decides which end of it the process goes at, by the size it's
+/// given once it's rounded up. The base has to be a [`FitPolicy`], see
+/// [`Backing`] for which ones there are.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct TwoEnded<P> {
+    base: P,
+    threshold: Size,
+}
+
+impl<P> TwoEnded<P> {
+    pub fn new(base: P, threshold: Size) -> Self {
+        Self { base, threshold }
+    }
+}
+
+impl<P: FitPolicy> FitPolicy for TwoEnded<P> {
+    fn name(&self) -> &'static str {
+        "Two-Ended"
+    }
+
+    fn select(&mut self, req: &MemoryRequest, holes: &Holes) -> Option<Hole> {
+        self.base.select(req, holes)
+    }
+
+    fn at_top(&self, req: &MemoryRequest) -> bool {
+        req.size >= self.threshold
+    }
+
+    fn placed(&mut self, req: &MemoryRequest, hole: &Hole) {
+        self.base.placed(req, hole);
+    }
+
+    fn classes(&self) -> Vec<ClassStats> {
+        self.base.classes()
+    }
+
+    fn ends(&self, mem: &Vector<MemoryRegion>) -> Option<(EndStats, EndStats)> {
+        Some(ends(mem, self.threshold))
+    }
+}
+
+/// The holes at one end of memory.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub struct EndStats {
+    pub holes: u32,
+    pub free: Size,
+}
+
+impl<P: FitPolicy> Fit<TwoEnded<P>> {
+    /// the holes in among the small processes at the bottom of memory, and
+    /// the big ones at the top. The hole in the middle isn't in either.
+    pub fn ends(&self) -> (EndStats, EndStats) {
+        ends(&self.mem, self.policy().threshold)
+    }
+}
+
+/// see [`Fit::ends`], with processes of `threshold` or bigger at the top.
+fn ends(mem: &Vector<MemoryRegion>, threshold: Size) -> (EndStats, EndStats) {
+    let regions = || {
+        mem.iter()
+            .zip(mem.iter().skip(1))
+            .map(|(region, next)| (region.0, region.1, next.1))
+    };
+    let mut low_top = 0;
+    let mut high_bottom = mem.last().expect("memory always has an end").1;
+    for (i, (owner, start, end)) in regions().enumerate() {
+        if !owner.is_process() {
+            continue;
+        }
+        // it was placed by the size it was given, rounding and all.
+        let end = match mem[i + 1].0 {
+            Owner::Rounding(..) => mem[i + 2].1,
+            _ => end,
+        };
+        if end - start >= threshold {
+            high_bottom = high_bottom.min(start);
+        } else {
+            low_top = low_top.max(end);
+        }
+    }
+    let (mut low, mut high) = (EndStats::default(), EndStats::default());
+    for (owner, start, end) in regions() {
+        let stats = match owner {
+            Owner::Free if end <= low_top => &mut low,
+            Owner::Free if start >= high_bottom => &mut high,
+            _ => continue,
+        };
+        stats.holes += 1;
+        stats.free += end - start;
+    }
+    (low, high)
+}
+
+/// A [`TwoEnded`] allocator with whichever base policy the options ask for.
+pub(super) fn with_base(mem_size: Size, options: AllocOptions) -> Box<dyn DynMemAllocator> {
+    let threshold = options.two_ended.threshold;
+    match options.two_ended.base {
+        Backing::Best => Box::new(
+            Fit::with_policy(mem_size, TwoEnded::new(Best, threshold)).with_options(options),
+        ),
+        Backing::First => Box::new(
+            Fit::with_policy(mem_size, TwoEnded::new(First, threshold)).with_options(options),
+        ),
+        Backing::Worst => Box::new(
+            Fit::with_policy(mem_size, TwoEnded::new(Worst, threshold)).with_options(options),
+        ),
+        Backing::Segregated => Box::new(
+            Fit::with_policy(mem_size, TwoEnded::new(Segregated::default(), threshold))
+                .with_options(options),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::strategies::{
+        AllocOptions, Fit, MemAllocatorMut, MemoryRegion, MemoryRequest, Owner, Pid, Worst,
+    };
+
+    use super::{EndStats, TwoEnded};
+
+    #[test]
+    fn small_at_the_bottom_big_at_the_top() {
+        let req = |pid, size| MemoryRequest {
+            process: Pid(pid),
+            size,
+            lifetime: 5,
+            align: 1,
+        };
+        let mut alloc = Fit::with_policy(128, TwoEnded::new(Worst, 16));
+        for (pid, size) in [(1, 10), (2, 20), (3, 5), (4, 30)] {
+            alloc.request(req(pid, size));
+        }
+        alloc.tick();
+        assert_eq!(
+            alloc.snapshot().layout(),
+            vec![
+                MemoryRegion(Owner::Process(Pid(1), 5), 0),
+                MemoryRegion(Owner::Process(Pid(3), 5), 10),
+                MemoryRegion(Owner::Free, 15),
+                MemoryRegion(Owner::Process(Pid(4), 5), 78),
+                MemoryRegion(Owner::Process(Pid(2), 5), 108),
+                MemoryRegion(Owner::End, 128),
+            ]
+        );
+        alloc.free(Pid(1));
+        alloc.free(Pid(2));
+        assert_eq!(
+            alloc.ends(),
+            (
+                EndStats { holes: 1, free: 10 },
+                EndStats { holes: 1, free: 20 }
+            )
+        );
+        assert_eq!(alloc.snapshot().ends(), Some(alloc.ends()));
+    }
+
+    #[test]
+    fn split_by_rounded_size() {
+        let options = AllocOptions {
+            unit: 8,
+            ..AllocOptions::default()
+        };
+        let mut alloc = Fit::with_policy(64, TwoEnded::new(Worst, 16)).with_options(options);
+        // rounded up to 16, which is big.
+        alloc.request(MemoryRequest {
+            process: Pid(1),
+            size: 13,
+            lifetime: 5,
+            align: 1,
+        });
+        alloc.tick();
+        assert_eq!(
+            alloc.snapshot().layout(),
+            vec![
+                MemoryRegion(Owner::Free, 0),
+                MemoryRegion(Owner::Process(Pid(1), 5), 48),
+                MemoryRegion(Owner::Rounding(Pid(1), 5), 61),
+                MemoryRegion(Owner::End, 64),
+            ]
+        );
+        assert_eq!(
+            alloc.ends(),
+            (EndStats::default(), EndStats::default()),
+            "the hole's below the only big process, so it's in the middle"
+        );
+    }
+}
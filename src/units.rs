@@ -0,0 +1,113 @@
+//! Parsing and pretty printing for the units used in the config.
+//! Sizes are stored in bytes and times are stored in ms. A bare size
+//! in the config is still KB, so it keeps meaning what it always has.
+
+const KB: u64 = 1024;
+const MB: u64 = 1024 * KB;
+const GB: u64 = 1024 * MB;
+const TB: u64 = 1024 * GB;
+
+const SIZE_UNITS: [(&str, u64); 10] = [
+    ("b", 1),
+    ("kb", KB),
+    ("k", KB),
+    ("mb", MB),
+    ("m", MB),
+    ("gb", GB),
+    ("g", GB),
+    ("tb", TB),
+    ("t", TB),
+    // no unit at all.
+    ("", KB),
+];
+const TIME_UNITS: [(&str, u64); 5] = [
+    ("ms", 1),
+    ("s", 1000),
+    ("min", 60_000),
+    ("m", 60_000),
+    ("", 1),
+];
+
+/// splits something like `"4mb"` into `("4", "mb")`.
+fn split_unit(s: &str) -> (&str, &str) {
+    let idx = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    s.split_at(idx)
+}
+
+/// multiplies the number by the unit, allowing fractional
+/// numbers (e.g. `1.5mb`) as long as the result is whole.
+fn scale(num: &str, multiplier: u64) -> Option<u64> {
+    if let Ok(whole) = num.parse::<u64>() {
+        return whole.checked_mul(multiplier);
+    }
+    let scaled = num.parse::<f64>().ok()? * multiplier as f64;
+    if scaled.fract() != 0.0 || scaled < 0.0 || scaled > u64::MAX as f64 {
+        return None;
+    }
+    Some(scaled as u64)
+}
+
+fn parse_with(s: &str, units: &[(&str, u64)]) -> Option<u64> {
+    let s = s.trim().to_lowercase();
+    let (num, unit) = split_unit(&s);
+    let (_, multiplier) = units.iter().find(|(name, _)| *name == unit)?;
+    scale(num, *multiplier)
+}
+
+/// Parses a size such as `256`, `512B`, `256KB`, `4MB` or `1GB` into bytes.
+/// A bare number is KB.
+pub fn parse_size(s: &str) -> Option<u64> {
+    parse_with(s, &SIZE_UNITS)
+}
+
+/// Parses a duration such as `10000`, `250ms`, `10s` or `2min` into ms.
+pub fn parse_duration(s: &str) -> Option<u32> {
+    parse_with(s, &TIME_UNITS)?.try_into().ok()
+}
+
+/// Formats a size in bytes using the biggest unit that keeps it above 1,
+/// e.g. `12B`, `512KB`, `4MB`, `1.5GB`.
+pub fn format_size(bytes: u64) -> String {
+    let (unit, div) = [("TB", TB), ("GB", GB), ("MB", MB), ("KB", KB)]
+        .into_iter()
+        .find(|&(_, div)| bytes >= div)
+        .unwrap_or(("B", 1));
+    if bytes.is_multiple_of(div) {
+        format!("{}{unit}", bytes / div)
+    } else {
+        format!("{:.1}{unit}", bytes as f64 / div as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_size("32"), Some(32 * KB));
+        assert_eq!(parse_size("100b"), Some(100));
+        assert_eq!(parse_size("256KB"), Some(256 * KB));
+        assert_eq!(parse_size("4mb"), Some(4 * MB));
+        assert_eq!(parse_size("1.5MB"), Some(1536 * KB));
+        assert_eq!(parse_size("1GB"), Some(GB));
+        assert_eq!(parse_size("16GB"), Some(16 * GB));
+        assert_eq!(parse_size("4parsecs"), None);
+        assert_eq!(parse_duration("9822"), Some(9822));
+        assert_eq!(parse_duration("10s"), Some(10_000));
+        assert_eq!(parse_duration("250ms"), Some(250));
+        assert_eq!(parse_duration("2min"), Some(120_000));
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(12), "12B");
+        assert_eq!(format_size(30 * KB), "30KB");
+        assert_eq!(format_size(4 * MB), "4MB");
+        assert_eq!(format_size(1536 * KB), "1.5MB");
+        assert_eq!(format_size(3 * GB), "3GB");
+        assert_eq!(format_size(6 * TB), "6TB");
+    }
+}